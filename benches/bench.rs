@@ -19,10 +19,10 @@ fn packed_bits_scope(c: &mut Criterion) { let mut packed_scope = PackedBitScope:
     let packed_lens = PackedBitScope::num_lens::<u8>();
 
     c.bench_function("packed_words_8", move |b| b.iter(|| {
-        packed_scope.adjust(0usize);
+        packed_scope.adjust(Abs(0));
         for _ in 0..packed_scope.shape() {
             (packed_lens.set)(&mut packed_scope, 1);
-            packed_scope.adjust(1isize);
+            packed_scope.adjust(Rel(1));
         }
     }));
 
@@ -34,10 +34,10 @@ fn packed_bits_scope(c: &mut Criterion) { let mut packed_scope = PackedBitScope:
     let packed_lens = PackedBitScope::num_lens::<u8>();
 
     c.bench_function("packed_words_1", move |b| b.iter(|| {
-        packed_scope.adjust(0usize);
+        packed_scope.adjust(Abs(0));
         for _ in 0..packed_scope.shape() {
             (packed_lens.set)(&mut packed_scope, 1);
-            packed_scope.adjust(1isize);
+            packed_scope.adjust(Rel(1));
         }
     }));
 }
@@ -51,10 +51,10 @@ fn packed_bits_scope_bool(c: &mut Criterion) {
     let packed_lens = PackedBitScope::bit_lens();
 
     c.bench_function("packed_bits", move |b| b.iter(|| {
-        packed_scope.adjust(0usize);
+        packed_scope.adjust(Abs(0));
         for _ in 0..packed_scope.shape() {
             (packed_lens.set)(&mut packed_scope, true);
-            packed_scope.adjust(1isize);
+            packed_scope.adjust(Rel(1));
         }
     }));
 }
@@ -76,7 +76,7 @@ fn vec_scope(c: &mut Criterion) {
     c.bench_function("vec_8", move |b| b.iter(|| {
         for _ in 0..vec_scope.shape() {
             vec_lens.set(&mut vec_scope, 1);
-            vec_scope.adjust(1isize);
+            vec_scope.adjust(Rel(1));
         }
     }));
 }
@@ -98,7 +98,7 @@ fn vec_scope_step(c: &mut Criterion) {
     c.bench_function("vec_8_step", move |b| b.iter(|| {
         for i in (0..vec_scope.shape()).step_by(STEP) {
             vec_lens.set(&mut vec_scope, 1);
-            vec_scope.adjust(i);
+            vec_scope.adjust(Abs(i));
         }
     }));
 }
@@ -115,7 +115,7 @@ fn bit_vec_scope(c: &mut Criterion) {
     c.bench_function("bit_vec_8_random", move |b| b.iter(|| {
         for _ in 0..bit_vec_scope.shape() {
             (bit_vec_lens.set)(&mut bit_vec_scope, true);
-            bit_vec_scope.adjust(1isize);
+            bit_vec_scope.adjust(Rel(1));
         }
     }));
 }
@@ -130,10 +130,10 @@ fn packed_bit_8_random_access(c: &mut Criterion) {
     let mut packed_scope = PackedBitScope::with_words(vec!(0; LENGTH), 8);
     let packed_lens = PackedBitScope::num_lens::<u8>();
     c.bench_function("packed_words_8_random", move |b| b.iter(|| {
-        packed_scope.adjust(0usize);
+        packed_scope.adjust(Abs(0));
         for index in indices.iter() {
             (packed_lens.set)(&mut packed_scope, 1);
-            packed_scope.adjust(*index);
+            packed_scope.adjust(Abs(*index));
         }
     }));
 }
@@ -151,10 +151,10 @@ fn packed_bit_1_random_access(c: &mut Criterion) {
     let mut packed_scope = PackedBitScope::with_words(vec!(0; num_bytes_needed), 1);
     let packed_lens = PackedBitScope::num_lens::<u8>();
     c.bench_function("packed_words_1_random", move |b| b.iter(|| {
-        packed_scope.adjust(0usize);
+        packed_scope.adjust(Abs(0));
         for index in indices.iter() {
             (packed_lens.set)(&mut packed_scope, 1);
-            packed_scope.adjust(*index);
+            packed_scope.adjust(Abs(*index));
         }
     }));
 }
@@ -170,7 +170,7 @@ fn vec_random_access(c: &mut Criterion) {
     c.bench_function("vec_8_random", move |b| b.iter(|| {
         for index in indices.iter() {
             vec_lens.set(&mut vec_scope, 1);
-            vec_scope.adjust(*index);
+            vec_scope.adjust(Abs(*index));
         }
     }));
 }
@@ -235,6 +235,21 @@ fn map_single(c: &mut Criterion) {
     }));
 }
 
+fn map_all(c: &mut Criterion) {
+    let mut vec_scope = VecScope::with_vec(vec!(0; LENGTH)).unwrap();
+    c.bench_function("map_all", move |b| b.iter(|| {
+        scope_map(&mut vec_scope, Action::new(VecScope::lens(), |val| val + 1));
+    }));
+
+    // a closure lens goes through the cursor for every element
+    let mut vec_scope = VecScope::with_vec(vec!(0u32; LENGTH)).unwrap();
+    c.bench_function("map_all_lens", move |b| b.iter(|| {
+        let lens = myopic::lens::lens::Lens::new(|vec: &VecScope<u32>| vec_scope::get_vec_scope(vec),
+                                                 |vec: &mut VecScope<u32>, a: u32| vec_scope::set_vec_scope(vec, a));
+        scope_map(&mut vec_scope, Action::new(lens, |val| val + 1));
+    }));
+}
+
 fn map_both(c: &mut Criterion) {
     let mut vec_scope = VecScope::with_vec(vec!(0; LENGTH)).unwrap();
     let transform1 =
@@ -255,16 +270,18 @@ fn map_both(c: &mut Criterion) {
 fn map_many_2(c: &mut Criterion) {
     let mut vec_scope = VecScope::with_vec(vec!(0; LENGTH)).unwrap();
 
+    let increment = |val| val + 1;
+
     c.bench_function("map_many_2", move |b| b.iter(|| {
         let transform1 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transform2 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
         let transforms = vec!(transform1, transform2);
         apply_many(transforms, &mut vec_scope);
     }));
@@ -273,21 +290,23 @@ fn map_many_2(c: &mut Criterion) {
 fn map_many_3(c: &mut Criterion) {
     let mut vec_scope = VecScope::with_vec(vec!(0; LENGTH)).unwrap();
 
+    let increment = |val| val + 1;
+
     c.bench_function("map_many_3", move |b| b.iter(|| {
         let transform1 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transform2 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transform3 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
         let transforms = vec!(transform1, transform2, transform3);
         apply_many(transforms, &mut vec_scope);
     }));
@@ -296,35 +315,106 @@ fn map_many_3(c: &mut Criterion) {
 fn map_many_4(c: &mut Criterion) {
     let mut vec_scope = VecScope::with_vec(vec!(0; LENGTH)).unwrap();
 
+    let increment = |val| val + 1;
+
     c.bench_function("map_many_4", move |b| b.iter(|| {
         let transform1 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transform2 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transform3 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transform4 =
             Transform::make_transform(VecScope::lens(),
                                       (0..LENGTH).step_by(STEP),
-                                      |val| val + 1);
+                                      increment);
 
         let transforms = vec!(transform1, transform2, transform3, transform4);
         apply_many(transforms, &mut vec_scope);
     }));
 }
 
+fn bit_vec_fill(c: &mut Criterion) {
+    let mut bit_vec_scope = BitVecScope::with_bytes(vec!(0; LENGTH / 8)).unwrap();
+    let bit_vec_lens = BitVecScope::lens();
+    let range: Range<usize> = 3..(LENGTH - 5);
+
+    let loop_range = range.clone();
+    c.bench_function("bit_vec_fill_per_bit", move |b| b.iter(|| {
+        bit_vec_scope.adjust(Abs(loop_range.start));
+        for _ in loop_range.clone() {
+            (bit_vec_lens.set)(&mut bit_vec_scope, true);
+            bit_vec_scope.adjust(Rel(1));
+        }
+    }));
+
+    let mut bit_vec_scope = BitVecScope::with_bytes(vec!(0; LENGTH / 8)).unwrap();
+    c.bench_function("bit_vec_fill_bulk", move |b| b.iter(|| {
+        bit_vec_scope.fill_range(range.clone(), true);
+    }));
+}
+
+fn bit_vec_count(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let bytes: Vec<u8> = (0..(LENGTH / 8)).map(|_| rng.gen()).collect();
+    let mut bit_vec_scope = BitVecScope::with_bytes(bytes.clone()).unwrap();
+    let bit_vec_lens = BitVecScope::lens();
+
+    c.bench_function("bit_vec_count_per_bit", move |b| b.iter(|| {
+        let mut count = 0;
+        bit_vec_scope.adjust(Abs(0));
+        for _ in 0..bit_vec_scope.shape() {
+            count += (bit_vec_lens.view)(&bit_vec_scope) as usize;
+            bit_vec_scope.adjust(Rel(1));
+        }
+        count
+    }));
+
+    let bit_vec_scope = BitVecScope::with_bytes(bytes).unwrap();
+    c.bench_function("bit_vec_count_bulk", move |b| b.iter(|| {
+        bit_vec_scope.count_ones_range(3..(LENGTH - 5))
+    }));
+}
+
+fn bit_vec_or(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let lhs_bytes: Vec<u8> = (0..(LENGTH / 8)).map(|_| rng.gen()).collect();
+    let rhs_bytes: Vec<u8> = (0..(LENGTH / 8)).map(|_| rng.gen()).collect();
+
+    let mut lhs = BitVecScope::with_bytes(lhs_bytes.clone()).unwrap();
+    let mut rhs = BitVecScope::with_bytes(rhs_bytes.clone()).unwrap();
+    let bit_vec_lens = BitVecScope::lens();
+    c.bench_function("bit_vec_or_per_bit", move |b| b.iter(|| {
+        lhs.adjust(Abs(0));
+        rhs.adjust(Abs(0));
+        for _ in 0..lhs.shape() {
+            let bit = (bit_vec_lens.view)(&lhs) | (bit_vec_lens.view)(&rhs);
+            (bit_vec_lens.set)(&mut lhs, bit);
+            lhs.adjust(Rel(1));
+            rhs.adjust(Rel(1));
+        }
+    }));
+
+    let mut lhs = BitVecScope::with_bytes(lhs_bytes).unwrap();
+    let rhs = BitVecScope::with_bytes(rhs_bytes).unwrap();
+    c.bench_function("bit_vec_or_bulk", move |b| b.iter(|| {
+        lhs.or(&rhs);
+    }));
+}
+
 criterion_group!(packing, packed_bits_scope, packed_bits_scope_bool, vec_scope, bit_vec_scope, vec_raw, vec_raw_step, vec_scope_step);
 criterion_group!(random, packed_bit_8_random_access, packed_bit_1_random_access, vec_random_access);
-criterion_group!(mapping, map_single, map_seq, map_seq_4, map_both, map_many_2, map_many_3, map_many_4);
+criterion_group!(mapping, map_all, map_single, map_seq, map_seq_4, map_both, map_many_2, map_many_3, map_many_4);
+criterion_group!(bulk, bit_vec_fill, bit_vec_count, bit_vec_or);
 
-criterion_main!(packing, random, mapping);
+criterion_main!(packing, random, mapping, bulk);
 
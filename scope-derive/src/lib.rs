@@ -0,0 +1,114 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Index};
+
+
+// #[derive(Lenses)] generates a lens type for each field of a struct,
+// implementing myopic's Lensable, Getter, Setter and Optical so that it
+// composes with ComposedLens and the scope lenses, and SliceLens so it is
+// an Over. The generated code names these through the scope crate's
+// re-exports, so use the derive as scope::Lenses. For a struct Record
+// with a field pos, the lens is RecordPosLens, built by Record::pos_lens().
+// Tuple struct fields are numbered, giving Record0Lens and Record::lens_0().
+//
+// Getting a field clones it, so field types must be Clone. A field whose
+// type derives Lenses itself can be focused further by composing its lens
+// with the inner struct's field lenses.
+#[proc_macro_derive(Lenses)]
+pub fn derive_lenses(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(&input.ident, "Lenses can only be derived for structs")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut lenses = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let (access, lens_name, constructor) = match fields {
+            Fields::Named(_) => {
+                let ident = field.ident.as_ref().unwrap();
+                let field_name = ident.to_string();
+                let field_name = field_name.trim_start_matches("r#");
+                (quote!(#ident),
+                 Ident::new(&format!("{}{}Lens", name, camel_case(field_name)), Span::call_site()),
+                 Ident::new(&format!("{}_lens", field_name), Span::call_site()))
+            },
+
+            _ => {
+                let member = Index::from(index);
+                (quote!(#member),
+                 Ident::new(&format!("{}{}Lens", name, index), Span::call_site()),
+                 Ident::new(&format!("lens_{}", index), Span::call_site()))
+            },
+        };
+        let ty = &field.ty;
+
+        lenses.push(quote! {
+            #vis struct #lens_name #impl_generics (::std::marker::PhantomData<fn() -> #name #ty_generics>) #where_clause;
+
+            impl #impl_generics ::scope::myopic::Lensable for #lens_name #ty_generics #where_clause {
+                type Input = #name #ty_generics;
+                type Output = #ty;
+            }
+
+            impl #impl_generics ::scope::myopic::Getter for #lens_name #ty_generics #where_clause {
+                fn get(&self, d: &#name #ty_generics) -> #ty {
+                    ::std::clone::Clone::clone(&d.#access)
+                }
+            }
+
+            impl #impl_generics ::scope::myopic::Setter for #lens_name #ty_generics #where_clause {
+                fn set(&self, d: &mut #name #ty_generics, a: #ty) {
+                    d.#access = a;
+                }
+            }
+
+            impl #impl_generics ::scope::myopic::Optical for #lens_name #ty_generics #where_clause {
+            }
+
+            impl #impl_generics ::scope::optic::SliceLens for #lens_name #ty_generics #where_clause {
+            }
+
+            impl #impl_generics #name #ty_generics #where_clause {
+                #vis fn #constructor() -> #lens_name #ty_generics {
+                    #lens_name(::std::marker::PhantomData)
+                }
+            }
+        });
+    }
+
+    let expanded = quote! {
+        #(#lenses)*
+    };
+
+    expanded.into()
+}
+
+// field_name to FieldName, for lens type names
+fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = true;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
@@ -0,0 +1,463 @@
+extern crate scope;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::process;
+use std::rc::Rc;
+
+use scope::*;
+use scope::bit_vec_scope::{get_vec_scope, set_vec_scope};
+
+
+const USAGE: &str = "\
+usage: scope <command> <file> [options] [arguments]
+
+commands:
+    dump  <file>                  print every field, or --count fields from --start
+    read  <file> <index>          print the field at index
+    write <file> <index> <value>  set the field at index to value
+    apply <file> <spec>           apply a transform spec to the fields in place,
+                                  with the fields named 'value', for example
+                                  'every 2 from 0..100 of field value: add 1'.
+                                  Values in the spec must fit in the field
+                                  width, and add wraps around at that width.
+
+options:
+    --width <bits>    field width in bits, 1 to 64 (default 8). A width of 1
+                      treats the file as a bit vector.
+    --offset <bytes>  number of bytes to skip at the start of the file (default 0)
+    --start <index>   first field to dump (default 0)
+    --count <n>       number of fields to dump (default all)
+    --json            print results and errors as JSON
+    --help            print this message
+
+exit codes:
+    0  success
+    1  the file could not be read or written
+    2  invalid command line
+    3  invalid index, value or spec";
+
+const EXIT_IO: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_INVALID: i32 = 3;
+
+
+#[derive(Debug, PartialEq)]
+struct CliError {
+    code: i32,
+    message: String,
+}
+
+impl CliError {
+    fn usage(message: String) -> CliError {
+        CliError { code: EXIT_USAGE, message }
+    }
+
+    fn invalid(message: String) -> CliError {
+        CliError { code: EXIT_INVALID, message }
+    }
+
+    fn io(message: String) -> CliError {
+        CliError { code: EXIT_IO, message }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Dump { start: usize, count: Option<usize> },
+    Read { index: usize },
+    Write { index: usize, value: u64 },
+    Apply { spec: String },
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    file: String,
+    width: usize,
+    offset: usize,
+    json: bool,
+}
+
+fn parse_num(name: &str, text: &str) -> Result<u64, CliError> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    parsed.map_err(|_| CliError::usage(format!("invalid {} '{}'", name, text)))
+}
+
+fn parse_args(args: &[String]) -> Result<Args, CliError> {
+    let mut positional = Vec::new();
+    let mut width = 8;
+    let mut offset = 0;
+    let mut start = 0;
+    let mut count = None;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next().ok_or_else(|| CliError::usage(format!("missing value for {}", name)))
+        };
+
+        match arg.as_str() {
+            "--width" => width = parse_num("width", value("--width")?)? as usize,
+            "--offset" => offset = parse_num("offset", value("--offset")?)? as usize,
+            "--start" => start = parse_num("start", value("--start")?)? as usize,
+            "--count" => count = Some(parse_num("count", value("--count")?)? as usize),
+            "--json" => json = true,
+            arg if arg.starts_with("--") => return Err(CliError::usage(format!("unknown option '{}'", arg))),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if width == 0 || width > 64 {
+        return Err(CliError::usage(format!("width must be from 1 to 64 bits, not {}", width)));
+    }
+
+    if positional.len() < 2 {
+        return Err(CliError::usage(USAGE.to_string()));
+    }
+
+    let expected = match positional[0].as_str() {
+        "dump" => 2,
+        "read" | "apply" => 3,
+        "write" => 4,
+        command => return Err(CliError::usage(format!("unknown command '{}'", command))),
+    };
+
+    if positional.len() != expected {
+        return Err(CliError::usage(format!("'{}' expects {} arguments, found {}",
+                                           positional[0], expected - 1, positional.len() - 1)));
+    }
+
+    let command = match positional[0].as_str() {
+        "dump" => Command::Dump { start, count },
+        "read" => Command::Read { index: parse_num("index", &positional[2])? as usize },
+        "write" => Command::Write { index: parse_num("index", &positional[2])? as usize,
+                                    value: parse_num("value", &positional[3])? },
+        _ => Command::Apply { spec: positional[2].clone() },
+    };
+
+    Ok(Args {
+        command,
+        file: positional[1].clone(),
+        width,
+        offset,
+        json,
+    })
+}
+
+
+// The fields of a file, either as single bits or packed fields of a
+// given width.
+enum FileScope {
+    Bits(BitVecScope),
+    Packed(PackedBitScope),
+}
+
+impl FileScope {
+    fn new(bytes: Vec<u8>, width: usize) -> Result<FileScope, CliError> {
+        if width == 1 {
+            BitVecScope::with_bytes(bytes)
+                .map(FileScope::Bits)
+                .ok_or_else(|| CliError::invalid("no bytes to read after the offset".to_string()))
+        } else {
+            Ok(FileScope::Packed(PackedBitScope::with_words(bytes, width)))
+        }
+    }
+
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            FileScope::Bits(scope) => scope.bytes,
+            FileScope::Packed(scope) => scope.bytes,
+        }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            FileScope::Bits(_) => 1,
+            FileScope::Packed(scope) => scope.bits_used,
+        }
+    }
+
+    fn lens() -> Lens<FileScope, u64> {
+        lens(Rc::new(|scope: &FileScope| match scope {
+                 FileScope::Bits(scope) => get_vec_scope(scope) as u64,
+                 FileScope::Packed(scope) => get_packedbit_scope_num(scope),
+             }),
+             Rc::new(|scope: &mut FileScope, value: u64| match scope {
+                 FileScope::Bits(scope) => set_vec_scope(scope, value != 0),
+                 FileScope::Packed(scope) => set_packedbit_scope_num(scope, value),
+             }))
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), CliError> {
+        if index >= self.shape() {
+            Err(CliError::invalid(format!("index {} out of range for {} fields", index, self.shape())))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get(&mut self, index: usize) -> Result<u64, CliError> {
+        self.check_index(index)?;
+        self.adjust(index);
+        Ok((FileScope::lens().view)(self))
+    }
+
+    fn check_value(&self, value: u64) -> Result<(), CliError> {
+        if self.width() < 64 && value >> self.width() != 0 {
+            Err(CliError::invalid(format!("value {} does not fit in {} bits", value, self.width())))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_op(&self, op: &Op<u64>) -> Result<(), CliError> {
+        match *op {
+            Op::Set(value) | Op::Add(value) | Op::Xor(value) | Op::Mask(value) => self.check_value(value),
+            Op::Clamp(low, high) => self.check_value(low).and_then(|_| self.check_value(high)),
+        }
+    }
+
+    fn set(&mut self, index: usize, value: u64) -> Result<(), CliError> {
+        self.check_index(index)?;
+        self.check_value(value)?;
+
+        self.adjust(index);
+        (FileScope::lens().set)(self, value);
+        Ok(())
+    }
+}
+
+impl Shape for FileScope {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        match self {
+            FileScope::Bits(scope) => scope.shape(),
+            FileScope::Packed(scope) => scope.shape(),
+        }
+    }
+}
+
+impl Scope<usize> for FileScope {
+    fn adjust(&mut self, index: usize) {
+        match self {
+            FileScope::Bits(scope) => scope.adjust(index),
+            FileScope::Packed(scope) => scope.adjust(index),
+        }
+    }
+}
+
+
+fn run(args: &Args) -> Result<String, CliError> {
+    let mut bytes = fs::read(&args.file)
+        .map_err(|err| CliError::io(format!("could not read '{}': {}", args.file, err)))?;
+
+    if args.offset > bytes.len() {
+        return Err(CliError::invalid(format!("offset {} is past the end of the {} byte file",
+                                             args.offset, bytes.len())));
+    }
+    let body = bytes.split_off(args.offset);
+    let mut scope = FileScope::new(body, args.width)?;
+
+    let (output, modified) = match args.command {
+        Command::Dump { start, count } => {
+            let end = match count {
+                Some(count) => start.saturating_add(count).min(scope.shape()),
+                None => scope.shape(),
+            };
+
+            let mut values = Vec::new();
+            for index in start..end {
+                values.push(scope.get(index)?);
+            }
+
+            let output = if args.json {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                format!("{{\"width\":{},\"offset\":{},\"start\":{},\"values\":[{}]}}",
+                        args.width, args.offset, start, values.join(","))
+            } else {
+                let lines: Vec<String> =
+                    values.iter().enumerate().map(|(index, value)| format!("{}: {}", start + index, value)).collect();
+                lines.join("\n")
+            };
+
+            (output, false)
+        }
+
+        Command::Read { index } => {
+            let value = scope.get(index)?;
+            let output = if args.json {
+                format!("{{\"index\":{},\"value\":{}}}", index, value)
+            } else {
+                value.to_string()
+            };
+
+            (output, false)
+        }
+
+        Command::Write { index, value } => {
+            let old = scope.get(index)?;
+            scope.set(index, value)?;
+            let output = if args.json {
+                format!("{{\"index\":{},\"old\":{},\"value\":{}}}", index, old, value)
+            } else {
+                format!("{}: {} -> {}", index, old, value)
+            };
+
+            (output, true)
+        }
+
+        Command::Apply { ref spec } => {
+            let mut registry = LensRegistry::new();
+            registry.insert("value", FileScope::lens());
+
+            let specs = parse_spec(spec).map_err(|err| CliError::invalid(err.render(spec)))?;
+            for statement in specs.iter() {
+                scope.check_op(&statement.op)?;
+            }
+
+            let transforms = build_spec(specs, &registry, scope.shape())
+                .map_err(|err| CliError::invalid(err.render(spec)))?;
+            apply_many(transforms, &mut scope);
+
+            let output = if args.json {
+                format!("{{\"applied\":true,\"fields\":{}}}", scope.shape())
+            } else {
+                String::new()
+            };
+
+            (output, true)
+        }
+    };
+
+    if modified {
+        bytes.extend(scope.bytes());
+        fs::write(&args.file, &bytes)
+            .map_err(|err| CliError::io(format!("could not write '{}': {}", args.file, err)))?;
+    }
+
+    Ok(output)
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let result = parse_args(&args).and_then(|args| run(&args));
+
+    match result {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+        }
+
+        Err(err) => {
+            if json {
+                println!("{{\"error\":{},\"code\":{}}}", json_string(&err.message), err.code);
+            } else {
+                eprintln!("{}", err);
+            }
+            process::exit(err.code);
+        }
+    }
+}
+
+
+#[test]
+fn test_cli_parse_args() {
+    let args: Vec<String> = "write data.bin 3 0x1f --width 9 --offset 2 --json"
+        .split(' ').map(|arg| arg.to_string()).collect();
+
+    assert_eq!(parse_args(&args),
+               Ok(Args {
+                   command: Command::Write { index: 3, value: 31 },
+                   file: "data.bin".to_string(),
+                   width: 9,
+                   offset: 2,
+                   json: true,
+               }));
+
+    let args: Vec<String> = vec!("read".to_string(), "data.bin".to_string());
+    assert_eq!(parse_args(&args).unwrap_err().code, EXIT_USAGE);
+
+    let args: Vec<String> = vec!("dump".to_string(), "data.bin".to_string(), "--width".to_string(), "65".to_string());
+    assert_eq!(parse_args(&args).unwrap_err().code, EXIT_USAGE);
+}
+
+#[test]
+fn test_cli_run() {
+    let path = env::temp_dir().join(format!("scope_cli_test_{}.bin", process::id()));
+    let file = path.to_str().unwrap().to_string();
+    fs::write(&path, vec!(0xAA, 0x00, 0x00, 0x00)).unwrap();
+
+    let args = |command: Command, width: usize, json: bool| Args {
+        command,
+        file: file.clone(),
+        width,
+        offset: 1,
+        json,
+    };
+
+    assert_eq!(run(&args(Command::Write { index: 1, value: 0x1FF }, 9, true)),
+               Ok("{\"index\":1,\"old\":0,\"value\":511}".to_string()));
+    assert_eq!(fs::read(&path).unwrap(), vec!(0xAA, 0x00, 0xFE, 0x03));
+
+    assert_eq!(run(&args(Command::Write { index: 0, value: 0x200 }, 9, false)).unwrap_err().code, EXIT_INVALID);
+    assert_eq!(run(&args(Command::Read { index: 2 }, 9, false)).unwrap_err().code, EXIT_INVALID);
+
+    assert_eq!(run(&args(Command::Apply { spec: "0..2 of field value: add 1".to_string() }, 9, false)),
+               Ok(String::new()));
+    assert_eq!(run(&args(Command::Dump { start: 0, count: None }, 9, true)),
+               Ok("{\"width\":9,\"offset\":1,\"start\":0,\"values\":[1,0]}".to_string()));
+    assert_eq!(run(&args(Command::Dump { start: 0, count: Some(3) }, 1, false)),
+               Ok("0: 1\n1: 0\n2: 0".to_string()));
+
+    // values that do not fit the field are refused before anything is changed
+    let err = run(&args(Command::Apply { spec: "0..2 of field value: set 512".to_string() }, 9, false)).unwrap_err();
+    assert_eq!(err.code, EXIT_INVALID);
+    assert_eq!(err.message, "value 512 does not fit in 9 bits");
+    assert_eq!(run(&args(Command::Apply { spec: "0 of field value: xor 0x200".to_string() }, 9, false)).unwrap_err().code,
+               EXIT_INVALID);
+    assert_eq!(run(&args(Command::Read { index: 0 }, 9, false)), Ok("1".to_string()));
+
+    let err = run(&args(Command::Apply { spec: "0..2 of field val: add 1".to_string() }, 9, false)).unwrap_err();
+    assert_eq!(err.code, EXIT_INVALID);
+    assert!(err.message.contains("unknown field 'val'"));
+
+    fs::remove_file(&path).unwrap();
+}
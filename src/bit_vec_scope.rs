@@ -1,17 +1,43 @@
 use num::clamp;
+use std::convert::TryInto;
+use std::iter::FromIterator;
+use std::ops::Range;
 use std::rc::Rc;
 
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
 use crate::lens::*;
 use crate::scope::*;
 use crate::shape::*;
 
 
 #[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct BitVecScope {
     pub bytes: Vec<u8>,
     pub pos: usize,
 }
 
+// bits are packed least significant first, padding the last byte with
+// zeros. Panics if the iterator is empty.
+impl FromIterator<bool> for BitVecScope {
+    fn from_iter<It: IntoIterator<Item=bool>>(iter: It) -> BitVecScope {
+        BitVecScope::with_bytes(pack_bits(iter)).expect("BitVecScope needs at least one bit")
+    }
+}
+
+pub fn pack_bits<It: IntoIterator<Item=bool>>(iter: It) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (index, bit) in iter.into_iter().enumerate() {
+        if index % 8 == 0 {
+            bytes.push(0);
+        }
+        *bytes.last_mut().unwrap() |= (bit as u8) << (index % 8);
+    }
+    bytes
+}
+
 impl Shape for BitVecScope {
     type Shape = usize;
 
@@ -44,6 +70,172 @@ impl BitVecScope {
     pub fn current_byte(&self) -> u8 {
         self.bytes[self.byte_index()]
     }
+
+    // first position at or after from holding value
+    pub fn find_next(&self, from: usize, value: bool) -> Option<usize> {
+        let flip = if value { 0 } else { 0xFF };
+        let mut byte_index = from / 8;
+        let mut mask = 0xFFu8 << (from % 8);
+
+        while byte_index < self.bytes.len() {
+            let byte = (self.bytes[byte_index] ^ flip) & mask;
+            if byte != 0 {
+                return Some((byte_index * 8) + byte.trailing_zeros() as usize);
+            }
+            byte_index += 1;
+            mask = 0xFF;
+        }
+
+        None
+    }
+
+    // last position at or before from holding value
+    pub fn find_prev(&self, from: usize, value: bool) -> Option<usize> {
+        let flip = if value { 0 } else { 0xFF };
+        let from = from.min(self.shape() - 1);
+        let mut byte_index = from / 8;
+        let mut mask = 0xFFu8 >> (7 - (from % 8));
+
+        loop {
+            let byte = (self.bytes[byte_index] ^ flip) & mask;
+            if byte != 0 {
+                return Some((byte_index * 8) + 7 - byte.leading_zeros() as usize);
+            }
+            if byte_index == 0 {
+                return None;
+            }
+            byte_index -= 1;
+            mask = 0xFF;
+        }
+    }
+
+    // move the cursor to the next position after it holding value, leaving
+    // it in place if there is none.
+    pub fn seek_next(&mut self, value: bool) -> Option<usize> {
+        let found = self.find_next(self.pos + 1, value);
+        if let Some(pos) = found {
+            self.pos = pos;
+        }
+        found
+    }
+
+    pub fn seek_prev(&mut self, value: bool) -> Option<usize> {
+        if self.pos == 0 {
+            return None;
+        }
+
+        let found = self.find_prev(self.pos - 1, value);
+        if let Some(pos) = found {
+            self.pos = pos;
+        }
+        found
+    }
+}
+
+// Bulk operations work a byte or a 64 bit word at a time instead of going
+// through the lens once per bit. Ranges are clamped to the shape, and bits
+// outside a range in its first and last bytes are masked off.
+impl BitVecScope {
+    pub fn fill_range(&mut self, range: Range<usize>, value: bool) {
+        let (start, end) = self.clamp_range(range);
+        if start >= end {
+            return;
+        }
+
+        let (first, last) = (start / 8, (end - 1) / 8);
+        let first_mask = 0xFFu8 << (start % 8);
+        let last_mask = 0xFFu8 >> (7 - ((end - 1) % 8));
+
+        if first == last {
+            set_masked(&mut self.bytes[first], first_mask & last_mask, value);
+        } else {
+            set_masked(&mut self.bytes[first], first_mask, value);
+            for byte in self.bytes[(first + 1)..last].iter_mut() {
+                *byte = if value { 0xFF } else { 0 };
+            }
+            set_masked(&mut self.bytes[last], last_mask, value);
+        }
+    }
+
+    pub fn clear_range(&mut self, range: Range<usize>) {
+        self.fill_range(range, false);
+    }
+
+    pub fn count_ones_range(&self, range: Range<usize>) -> usize {
+        let (start, end) = self.clamp_range(range);
+        if start >= end {
+            return 0;
+        }
+
+        let (first, last) = (start / 8, (end - 1) / 8);
+        let first_mask = 0xFFu8 << (start % 8);
+        let last_mask = 0xFFu8 >> (7 - ((end - 1) % 8));
+
+        if first == last {
+            (self.bytes[first] & first_mask & last_mask).count_ones() as usize
+        } else {
+            (self.bytes[first] & first_mask).count_ones() as usize +
+                count_ones_bytes(&self.bytes[(first + 1)..last]) +
+                (self.bytes[last] & last_mask).count_ones() as usize
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        count_ones_bytes(&self.bytes)
+    }
+
+    // in place bitwise operations with a scope of the same shape
+    pub fn and(&mut self, other: &BitVecScope) {
+        self.combine(other, |lhs, rhs| lhs & rhs);
+    }
+
+    pub fn or(&mut self, other: &BitVecScope) {
+        self.combine(other, |lhs, rhs| lhs | rhs);
+    }
+
+    pub fn xor(&mut self, other: &BitVecScope) {
+        self.combine(other, |lhs, rhs| lhs ^ rhs);
+    }
+
+    pub fn and_not(&mut self, other: &BitVecScope) {
+        self.combine(other, |lhs, rhs| lhs & !rhs);
+    }
+
+    fn combine<F: Fn(u64, u64) -> u64>(&mut self, other: &BitVecScope, op: F) {
+        assert_eq!(self.shape(), other.shape(), "BitVecScope shapes differ");
+
+        let mut lhs = self.bytes.chunks_exact_mut(8);
+        let mut rhs = other.bytes.chunks_exact(8);
+        for (lhs_word, rhs_word) in (&mut lhs).zip(&mut rhs) {
+            let word = op(u64::from_le_bytes((&*lhs_word).try_into().unwrap()),
+                          u64::from_le_bytes(rhs_word.try_into().unwrap()));
+            lhs_word.copy_from_slice(&word.to_le_bytes());
+        }
+
+        for (lhs_byte, rhs_byte) in lhs.into_remainder().iter_mut().zip(rhs.remainder()) {
+            *lhs_byte = op(*lhs_byte as u64, *rhs_byte as u64) as u8;
+        }
+    }
+
+    fn clamp_range(&self, range: Range<usize>) -> (usize, usize) {
+        (range.start.min(self.shape()), range.end.min(self.shape()))
+    }
+}
+
+fn set_masked(byte: &mut u8, mask: u8, value: bool) {
+    if value {
+        *byte |= mask;
+    } else {
+        *byte &= !mask;
+    }
+}
+
+fn count_ones_bytes(bytes: &[u8]) -> usize {
+    let words = bytes.chunks_exact(8);
+    let remainder: usize = words.remainder().iter().map(|byte| byte.count_ones() as usize).sum();
+    let whole: usize =
+        words.map(|word| u64::from_le_bytes(word.try_into().unwrap()).count_ones() as usize).sum();
+    whole + remainder
 }
 
 pub fn get_vec_scope(bit_vec_scope: &BitVecScope) -> bool {
@@ -58,6 +250,12 @@ pub fn set_vec_scope(bit_vec_scope: &mut BitVecScope, a: bool) {
         (bit_vec_scope.current_byte() & !(1 << bit_index)) | ((a as u8) << bit_index);
 }
 
+impl Position for BitVecScope {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
 impl Scope<usize> for BitVecScope {
     fn adjust(&mut self, pos: usize) {
         self.pos = clamp(pos, 0, (self.bytes.len() * 8) - 1);
@@ -70,6 +268,8 @@ impl Scope<isize> for BitVecScope {
     }
 }
 
+crate::impl_typed_indices!([] BitVecScope);
+
 #[test]
 fn test_bit_vec_scope() {
     let mut bit_vec_scope = BitVecScope::with_bytes(vec![1,2,3,4,0x80]).unwrap();
@@ -88,3 +288,72 @@ fn test_bit_vec_scope() {
     bit_vec_scope.adjust(100isize);
     assert_eq!((lens.view)(&bit_vec_scope), true);
 }
+
+#[test]
+fn test_bit_vec_scope_navigation() {
+    let mut bit_vec_scope = BitVecScope::with_bytes(vec![0x01, 0x00, 0x00, 0x90, 0xFF]).unwrap();
+
+    assert_eq!(bit_vec_scope.seek_next(true), Some(28));
+    assert_eq!(bit_vec_scope.seek_next(true), Some(31));
+    assert_eq!(bit_vec_scope.seek_next(false), None);
+    assert_eq!(bit_vec_scope.position(), 31);
+
+    assert_eq!(bit_vec_scope.seek_prev(true), Some(28));
+    assert_eq!(bit_vec_scope.seek_prev(false), Some(27));
+    assert_eq!(bit_vec_scope.seek_prev(true), Some(0));
+    assert_eq!(bit_vec_scope.seek_prev(true), None);
+    assert_eq!(bit_vec_scope.seek_next(false), Some(1));
+
+    assert_eq!(bit_vec_scope.find_prev(100, false), Some(30));
+    assert_eq!(bit_vec_scope.find_next(40, true), None);
+}
+
+#[test]
+fn test_bit_vec_scope_bulk() {
+    let mut bit_vec_scope = BitVecScope::with_bytes(vec!(0; 20)).unwrap();
+
+    bit_vec_scope.fill_range(3..5, true);
+    assert_eq!(bit_vec_scope.bytes[0], 0x18);
+
+    bit_vec_scope.fill_range(13..150, true);
+    assert_eq!(bit_vec_scope.count_ones(), 2 + 137);
+    assert_eq!(bit_vec_scope.bytes[1], 0xE0);
+    assert_eq!(bit_vec_scope.bytes[18], 0x3F);
+    assert_eq!(bit_vec_scope.count_ones_range(0..16), 5);
+    assert_eq!(bit_vec_scope.count_ones_range(140..1000), 10);
+
+    bit_vec_scope.clear_range(14..149);
+    assert_eq!(bit_vec_scope.count_ones(), 4);
+    assert!(bit_vec_scope.find_next(5, true) == Some(13));
+    assert!(bit_vec_scope.find_next(14, true) == Some(149));
+
+    // compare the word operations with a per-bit loop
+    let lhs_bytes: Vec<u8> = (0..20).map(|index| (index * 37) as u8).collect();
+    let rhs_bytes: Vec<u8> = (0..20).map(|index| (index * 91 + 5) as u8).collect();
+    let rhs = BitVecScope::with_bytes(rhs_bytes.clone()).unwrap();
+
+    type WordOp = (fn(&mut BitVecScope, &BitVecScope), fn(u8, u8) -> u8);
+    let ops: Vec<WordOp> =
+        vec!((BitVecScope::and, |l, r| l & r),
+             (BitVecScope::or, |l, r| l | r),
+             (BitVecScope::xor, |l, r| l ^ r),
+             (BitVecScope::and_not, |l, r| l & !r));
+    for (op, byte_op) in ops {
+        let mut lhs = BitVecScope::with_bytes(lhs_bytes.clone()).unwrap();
+        op(&mut lhs, &rhs);
+        let expected: Vec<u8> =
+            lhs_bytes.iter().zip(rhs_bytes.iter()).map(|(l, r)| byte_op(*l, *r)).collect();
+        assert_eq!(lhs.bytes, expected);
+    }
+}
+
+#[test]
+fn test_bit_vec_scope_iter() {
+    let mut bit_vec_scope: BitVecScope = (0..10).map(|index| index % 3 == 0).collect();
+    assert_eq!(bit_vec_scope.bytes, vec!(0x49, 0x02));
+
+    let lens = BitVecScope::lens();
+    let bits: Vec<bool> = bit_vec_scope.iter_with(&lens).take(10).collect();
+    assert_eq!(bits, (0..10).map(|index| index % 3 == 0).collect::<Vec<_>>());
+    assert_eq!(bit_vec_scope.iter_with(&lens).count(), 16);
+}
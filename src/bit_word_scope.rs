@@ -1,3 +1,5 @@
+use std::iter::FromIterator;
+use std::mem;
 use std::rc::Rc;
 //use std::ops::{Shl, ShlAssign, Shr, ShrAssign, Rem, RemAssign, BitOrAssign, BitXor, Not, Sub, BitAnd, BitOr};
 
@@ -6,6 +8,9 @@ use num::clamp;
 use num::PrimInt;
 use num::cast::NumCast;
 
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
 use crate::shape::*;
 use crate::lens::*;
 use crate::types::*;
@@ -13,14 +18,15 @@ use crate::scope::*;
 
 
 #[derive(Clone, PartialEq, Eq)]
-struct BitWordScope<B> {
-    vec: Vec<B>,
-    bits_used: usize,
-    pos: usize,
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BitWordScope<B> {
+    pub vec: Vec<B>,
+    pub bits_used: usize,
+    pub pos: usize,
 }
 
 impl<B> BitWordScope<B> {
-    fn with_words(vec: Vec<B>, bits_used: usize) -> BitWordScope<B> {
+    pub fn with_words(vec: Vec<B>, bits_used: usize) -> BitWordScope<B> {
         BitWordScope {
             vec: vec,
             bits_used: bits_used,
@@ -29,15 +35,24 @@ impl<B> BitWordScope<B> {
     }
 }
 
+// every bit of each word is used
+impl<B> FromIterator<B> for BitWordScope<B> {
+    fn from_iter<It: IntoIterator<Item=B>>(iter: It) -> BitWordScope<B> {
+        let vec: Vec<B> = iter.into_iter().collect();
+        assert!(!vec.is_empty(), "BitWordScope needs at least one word");
+        BitWordScope::with_words(vec, mem::size_of::<B>() * 8)
+    }
+}
+
 impl<B: PrimInt> BitWordScope<B> {
-    fn bit_lens() -> Lens<BitWordScope<B>, bool> {
+    pub fn bit_lens() -> Lens<BitWordScope<B>, bool> {
         lens(Rc::new(|vec: &BitWordScope<B>| get_bitword_scope_bits(vec)),
              Rc::new(|vec: &mut BitWordScope<B>, a: bool| set_bitword_scope_bits(vec, a)))
     }
 }
 
 impl<B: PrimInt> BitWordScope<B> {
-    fn lens() -> Lens<BitWordScope<B>, B> {
+    pub fn lens() -> Lens<BitWordScope<B>, B> {
         lens(Rc::new(|vec: &BitWordScope<B>| get_bitword_scope(vec)),
              Rc::new(|vec: &mut BitWordScope<B>, a: B| set_bitword_scope(vec, a)))
     }
@@ -78,6 +93,12 @@ fn set_bitword_scope<B: PrimInt>(bitword_scope: &mut BitWordScope<B>, a: B) {
         bitword_scope.vec[index] = a;
 }
 
+impl<B> Position for BitWordScope<B> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
 impl<B> Scope<usize> for BitWordScope<B> {
     fn adjust(&mut self, pos: usize) {
         self.pos = clamp(pos, 0, (self.vec.len() * self.bits_used) - 1);
@@ -90,6 +111,8 @@ impl<B> Scope<isize> for BitWordScope<B> {
     }
 }
 
+crate::impl_typed_indices!([B] BitWordScope<B>);
+
 #[test]
 fn test_bit_word_scope() {
     let mut bit_word_scope: BitWordScope<u8> = BitWordScope::with_words(vec![1,2,3,4,0x7], 3);
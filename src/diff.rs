@@ -0,0 +1,366 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::bit_vec_scope::*;
+use crate::packed_bit_scope::*;
+use crate::vec_scope::*;
+
+
+/* Diff */
+// A Diff lists the edits that turn one VecScope's vec into another's. Each
+// edit replaces the values at [start, start + old.len()) with new. Keeping
+// the old values lets a patch check it is being applied to the vec it was
+// taken from, and lets a diff be inverted to revert it. Edits are ordered
+// by start and do not overlap. Only the last edit can change the length.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diff<A> {
+    pub len: usize,
+    pub edits: Vec<DiffEdit<A>>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DiffEdit<A> {
+    pub start: usize,
+    pub old: Vec<A>,
+    pub new: Vec<A>,
+}
+
+// A BitDiff does the same for the bytes of a BitVecScope or PackedBitScope,
+// bit by bit. Each edit sets the bits [start, start + len) to value, all of
+// which were !value before. Bit diffs never change the length. bits_used is
+// the field width of the scopes diffed, 1 for a BitVecScope, so a diff is
+// not applied to the same bytes read as other fields.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BitDiff {
+    pub len: usize,
+    pub bits_used: usize,
+    pub edits: Vec<BitEdit>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BitEdit {
+    pub start: usize,
+    pub len: usize,
+    pub value: bool,
+}
+
+// A patch applied to a scope that does not hold what the diff was taken
+// from. Nothing is changed when a patch fails.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PatchError {
+    // the scope's length, in elements or bits, is not the diff's
+    Length { expected: usize, found: usize },
+    // the value at index is not the one the edit replaces
+    Mismatch { index: usize },
+    // the scope's field width is not the diff's
+    Width { expected: usize, found: usize },
+}
+
+impl<A: Clone> Diff<A> {
+    // the diff from the new vec back to the old one. None if the edits
+    // remove more values than the vec holds.
+    pub fn inverse(&self) -> Option<Diff<A>> {
+        Some(Diff {
+            len: self.patched_len()?,
+            edits: self.edits.iter().map(|edit| DiffEdit {
+                start: edit.start,
+                old: edit.new.clone(),
+                new: edit.old.clone(),
+            }).collect(),
+        })
+    }
+}
+
+impl<A> Diff<A> {
+    // the length of a vec after patching. None if the edits remove more
+    // values than the vec holds, as in a malformed diff.
+    pub fn patched_len(&self) -> Option<usize> {
+        self.edits.iter().try_fold(self.len, |len, edit| len.checked_sub(edit.old.len())?.checked_add(edit.new.len()))
+    }
+}
+
+impl BitDiff {
+    pub fn inverse(&self) -> BitDiff {
+        BitDiff {
+            len: self.len,
+            bits_used: self.bits_used,
+            edits: self.edits.iter().map(|edit| BitEdit { value: !edit.value, ..*edit }).collect(),
+        }
+    }
+}
+
+impl<A: PartialEq + Clone> VecScope<A> {
+    pub fn diff(&self, other: &VecScope<A>) -> Diff<A> {
+        let common = self.vec.len().min(other.vec.len());
+        let mut edits = Vec::new();
+
+        let mut index = 0;
+        while index < common {
+            if self.vec[index] == other.vec[index] {
+                index += 1;
+                continue;
+            }
+
+            let start = index;
+            while index < common && self.vec[index] != other.vec[index] {
+                index += 1;
+            }
+            edits.push(DiffEdit {
+                start,
+                old: self.vec[start..index].to_vec(),
+                new: other.vec[start..index].to_vec(),
+            });
+        }
+
+        // a change of length is one edit at the end, joined to the last
+        // edit if that reaches the end of the common part
+        if self.vec.len() != other.vec.len() {
+            let joined = edits.last().is_some_and(|edit| edit.start + edit.old.len() == common);
+            if joined {
+                let edit = edits.last_mut().unwrap();
+                edit.old.extend_from_slice(&self.vec[common..]);
+                edit.new.extend_from_slice(&other.vec[common..]);
+            } else {
+                edits.push(DiffEdit {
+                    start: common,
+                    old: self.vec[common..].to_vec(),
+                    new: other.vec[common..].to_vec(),
+                });
+            }
+        }
+
+        Diff {
+            len: self.vec.len(),
+            edits,
+        }
+    }
+
+    // apply a diff taken from a vec equal to this one. The cursor stays
+    // where it is, clamped to the new length.
+    pub fn patch(&mut self, diff: &Diff<A>) -> Result<(), PatchError> {
+        if self.vec.len() != diff.len {
+            return Err(PatchError::Length { expected: diff.len, found: self.vec.len() });
+        }
+
+        // check every edit first so a failed patch changes nothing
+        let mut end = 0;
+        for edit in diff.edits.iter() {
+            let edit_end = match edit.start.checked_add(edit.old.len()) {
+                Some(edit_end) if edit.start >= end && edit_end <= self.vec.len() => edit_end,
+                _ => return Err(PatchError::Mismatch { index: edit.start }),
+            };
+
+            let old = &self.vec[edit.start..edit_end];
+            if let Some(offset) = old.iter().zip(edit.old.iter()).position(|(val, old_val)| val != old_val) {
+                return Err(PatchError::Mismatch { index: edit.start + offset });
+            }
+            end = edit_end;
+        }
+
+        if diff.patched_len() == Some(0) {
+            return Err(PatchError::Length { expected: diff.len, found: 0 });
+        }
+
+        // edits are applied back to front so earlier starts stay valid
+        for edit in diff.edits.iter().rev() {
+            self.vec.splice(edit.start..(edit.start + edit.old.len()), edit.new.iter().cloned());
+        }
+
+        self.pos = self.pos.min(self.vec.len() - 1);
+        Ok(())
+    }
+}
+
+impl BitVecScope {
+    // None if the scopes differ in length
+    pub fn diff(&self, other: &BitVecScope) -> Option<BitDiff> {
+        diff_bits(&self.bytes, &other.bytes, 1)
+    }
+
+    pub fn patch(&mut self, diff: &BitDiff) -> Result<(), PatchError> {
+        patch_bits(&mut self.bytes, 1, diff)
+    }
+}
+
+impl PackedBitScope {
+    // None if the scopes differ in length or field width
+    pub fn diff(&self, other: &PackedBitScope) -> Option<BitDiff> {
+        if self.bits_used != other.bits_used {
+            return None;
+        }
+        diff_bits(&self.bytes, &other.bytes, self.bits_used)
+    }
+
+    pub fn patch(&mut self, diff: &BitDiff) -> Result<(), PatchError> {
+        patch_bits(&mut self.bytes, self.bits_used, diff)
+    }
+}
+
+fn get_bit(bytes: &[u8], bit: usize) -> bool {
+    (bytes[bit / 8] & (1 << (bit % 8))) != 0
+}
+
+fn diff_bits(old: &[u8], new: &[u8], bits_used: usize) -> Option<BitDiff> {
+    if old.len() != new.len() {
+        return None;
+    }
+
+    let len = old.len() * 8;
+    let mut edits = Vec::new();
+
+    let mut bit = 0;
+    while bit < len {
+        // skip whole bytes that match
+        if bit % 8 == 0 && old[bit / 8] == new[bit / 8] {
+            bit += 8;
+            continue;
+        }
+
+        let value = get_bit(new, bit);
+        if get_bit(old, bit) == value {
+            bit += 1;
+            continue;
+        }
+
+        let start = bit;
+        while bit < len && get_bit(old, bit) != value && get_bit(new, bit) == value {
+            bit += 1;
+        }
+        edits.push(BitEdit { start, len: bit - start, value });
+    }
+
+    Some(BitDiff {
+        len,
+        bits_used,
+        edits,
+    })
+}
+
+fn patch_bits(bytes: &mut [u8], bits_used: usize, diff: &BitDiff) -> Result<(), PatchError> {
+    if bytes.len() * 8 != diff.len {
+        return Err(PatchError::Length { expected: diff.len, found: bytes.len() * 8 });
+    }
+
+    if bits_used != diff.bits_used {
+        return Err(PatchError::Width { expected: diff.bits_used, found: bits_used });
+    }
+
+    // edits are flipped in place, so they must be ordered and not overlap
+    // for the checks to hold once they are applied
+    let mut end = 0;
+    for edit in diff.edits.iter() {
+        let edit_end = match edit.start.checked_add(edit.len) {
+            Some(edit_end) if edit.start >= end && edit_end <= diff.len => edit_end,
+            _ => return Err(PatchError::Mismatch { index: edit.start }),
+        };
+
+        if let Some(bit) = (edit.start..edit_end).find(|bit| get_bit(bytes, *bit) == edit.value) {
+            return Err(PatchError::Mismatch { index: bit });
+        }
+        end = edit_end;
+    }
+
+    for edit in diff.edits.iter() {
+        for bit in edit.start..(edit.start + edit.len) {
+            bytes[bit / 8] ^= 1 << (bit % 8);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_vec_scope_diff() {
+    use crate::scope::*;
+
+    let old = VecScope::with_vec(vec!(1u32, 2, 3, 4, 5, 6, 7, 8)).unwrap();
+    let new = VecScope::with_vec(vec!(1u32, 20, 30, 4, 5, 60, 7, 8, 9, 10)).unwrap();
+
+    let diff = old.diff(&new);
+    assert_eq!(diff.edits.iter().map(|edit| edit.start).collect::<Vec<_>>(), vec!(1, 5, 8));
+    assert_eq!(diff.edits[0].new, vec!(20, 30));
+
+    let mut scope = old.clone();
+    scope.adjust(7usize);
+    scope.patch(&diff).unwrap();
+    assert_eq!(scope.vec, new.vec);
+    assert_eq!(scope.position(), 7);
+
+    // applying it again finds the values already changed
+    assert_eq!(scope.patch(&diff), Err(PatchError::Length { expected: 8, found: 10 }));
+    let mut other = old.clone();
+    other.vec[6] = 0;
+    other.vec[2] = 0;
+    assert_eq!(other.patch(&diff), Err(PatchError::Mismatch { index: 2 }));
+    assert_eq!(other.vec, vec!(1, 2, 0, 4, 5, 6, 0, 8));
+
+    // reverting shrinks the vec and clamps the cursor
+    scope.adjust(9usize);
+    scope.patch(&diff.inverse().unwrap()).unwrap();
+    assert_eq!(scope.vec, old.vec);
+    assert_eq!(scope.position(), 7);
+
+    assert!(old.diff(&old).edits.is_empty());
+
+    let overflowing = Diff { len: 8, edits: vec!(DiffEdit { start: usize::MAX, old: vec!(1), new: vec!(2) }) };
+    assert_eq!(scope.patch(&overflowing), Err(PatchError::Mismatch { index: usize::MAX }));
+
+    // a malformed diff removing more than the vec holds has no inverse
+    let malformed = Diff { len: 1, edits: vec!(DiffEdit { start: 0, old: vec!(1u32, 2), new: vec!() }) };
+    assert_eq!(malformed.patched_len(), None);
+    assert_eq!(malformed.inverse(), None);
+}
+
+#[test]
+fn test_bit_diff() {
+    use crate::scope::*;
+
+    let old = BitVecScope::with_bytes(vec!(0x0F, 0x00, 0xFF)).unwrap();
+    let new = BitVecScope::with_bytes(vec!(0xF0, 0x00, 0xFB)).unwrap();
+
+    let diff = old.diff(&new).unwrap();
+    assert_eq!(diff.edits, vec!(BitEdit { start: 0, len: 4, value: false },
+                                BitEdit { start: 4, len: 4, value: true },
+                                BitEdit { start: 18, len: 1, value: false }));
+
+    let mut scope = old.clone();
+    scope.patch(&diff).unwrap();
+    assert_eq!(scope.bytes, new.bytes);
+    assert_eq!(scope.patch(&diff), Err(PatchError::Mismatch { index: 0 }));
+    scope.patch(&diff.inverse()).unwrap();
+    assert_eq!(scope.bytes, old.bytes);
+    assert!(old.diff(&BitVecScope::with_bytes(vec!(0)).unwrap()).is_none());
+
+    // overlapping or overflowing edits are refused, leaving the bytes alone
+    let overlapping = BitDiff { len: 24, bits_used: 1, edits: vec!(BitEdit { start: 0, len: 4, value: false },
+                                                                  BitEdit { start: 2, len: 4, value: true }) };
+    assert_eq!(scope.patch(&overlapping), Err(PatchError::Mismatch { index: 2 }));
+    let overflowing = BitDiff { len: 24, bits_used: 1, edits: vec!(BitEdit { start: 8, len: usize::MAX, value: true }) };
+    assert_eq!(scope.patch(&overflowing), Err(PatchError::Mismatch { index: 8 }));
+    assert_eq!(scope.bytes, old.bytes);
+
+    // packed scopes diff their bytes the same way
+    let old = PackedBitScope::from_values(vec!(1u8, 2, 3, 4, 5), 3).unwrap();
+    let new = PackedBitScope::from_values(vec!(1u8, 2, 7, 4, 0), 3).unwrap();
+    let diff = old.diff(&new).unwrap();
+    assert_eq!(diff.edits.len(), 3);
+
+    let mut scope = old.clone();
+    scope.patch(&diff).unwrap();
+    assert_eq!(scope.to_values::<u8>(), Some(vec!(1, 2, 7, 4, 0)));
+    assert!(old.diff(&PackedBitScope::from_values(vec!(1u8, 2, 3, 4, 5), 4).unwrap()).is_none());
+
+    // a diff between 9 bit scopes does not apply to 12 bit fields of the same bytes
+    let old = PackedBitScope::with_words(vec!(0; 3), 9);
+    let mut new = old.clone();
+    new.adjust(1usize);
+    (PackedBitScope::num_lens::<u16>().set)(&mut new, 0x1FF);
+    let diff = old.diff(&new).unwrap();
+    let mut wider = PackedBitScope::with_words(vec!(0; 3), 12);
+    assert_eq!(wider.patch(&diff), Err(PatchError::Width { expected: 9, found: 12 }));
+    assert_eq!(wider.bytes, vec!(0; 3));
+    assert_eq!(BitVecScope::with_bytes(vec!(0; 3)).unwrap().patch(&diff), Err(PatchError::Width { expected: 9, found: 1 }));
+}
@@ -0,0 +1,207 @@
+use std::ops::Range;
+
+use myopic::Lensable;
+
+use crate::optic::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+// DirtyScope wraps a scope and keeps a bitmap with one bit per position,
+// set for every position written through a Dirty optic. The dirty
+// positions are handed out as sorted, non-overlapping ranges by take_dirty.
+// The bitmap grows as positions are marked, so it stays small for scopes
+// with an unbounded shape such as a StreamScope.
+pub struct DirtyScope<S> {
+    pub scope: S,
+    dirty: Vec<u64>,
+}
+
+impl<S> DirtyScope<S>
+    where S: Shape<Shape=usize> + Position {
+    pub fn new(scope: S) -> DirtyScope<S> {
+        DirtyScope {
+            scope,
+            dirty: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scope
+    }
+
+    pub fn mark(&mut self, pos: usize) {
+        if pos / 64 >= self.dirty.len() {
+            self.dirty.resize((pos / 64) + 1, 0);
+        }
+        self.dirty[pos / 64] |= 1 << (pos % 64);
+    }
+
+    pub fn is_dirty(&self, pos: usize) -> bool {
+        match self.dirty.get(pos / 64) {
+            Some(word) => (word & (1 << (pos % 64))) != 0,
+            None => false,
+        }
+    }
+
+    pub fn dirty_count(&self) -> usize {
+        self.dirty.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn dirty_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for (word_index, word) in self.dirty.iter().enumerate() {
+            let mut word = *word;
+            while word != 0 {
+                let start = word.trailing_zeros() as usize;
+                let len = (!(word >> start)).trailing_zeros() as usize;
+                let pos = (word_index * 64) + start;
+
+                // extend a range carried over from the previous word
+                match ranges.last_mut() {
+                    Some(ref mut range) if range.end == pos => range.end = pos + len,
+                    _ => ranges.push(pos..(pos + len)),
+                }
+
+                if start + len >= 64 {
+                    word = 0;
+                } else {
+                    word &= !0 << (start + len);
+                }
+            }
+        }
+
+        ranges
+    }
+
+    pub fn take_dirty(&mut self) -> Vec<Range<usize>> {
+        let ranges = self.dirty_ranges();
+        for word in self.dirty.iter_mut() {
+            *word = 0;
+        }
+        ranges
+    }
+}
+
+impl<S: Shape> Shape for DirtyScope<S> {
+    type Shape = S::Shape;
+
+    fn shape(&self) -> S::Shape {
+        self.scope.shape()
+    }
+}
+
+impl<S: Position> Position for DirtyScope<S> {
+    fn position(&self) -> usize {
+        self.scope.position()
+    }
+}
+
+impl<I, S: Scope<I>> Scope<I> for DirtyScope<S> {
+    fn adjust(&mut self, index: I) {
+        self.scope.adjust(index);
+    }
+}
+
+
+// An optic on a DirtyScope which writes through an optic on the inner
+// scope, marking the cursor position as dirty.
+pub struct Dirty<U> {
+    pub optic: U,
+}
+
+impl<U> Dirty<U> {
+    pub fn new(optic: U) -> Dirty<U> {
+        Dirty {
+            optic,
+        }
+    }
+}
+
+impl<U: Lensable> Lensable for Dirty<U> {
+    type Input = DirtyScope<U::Input>;
+    type Output = U::Output;
+}
+
+impl<U, S> Over for Dirty<U>
+    where U: Over + Lensable<Input=S>,
+          S: Shape<Shape=usize> + Position {
+    fn over<F>(&self, d: &mut DirtyScope<S>, mut f: F)
+        where F: FnMut(U::Output) -> U::Output {
+        // only mark the position if the optic had a focus there to set
+        let mut written = false;
+        self.optic.over(&mut d.scope, |a| {
+            written = true;
+            f(a)
+        });
+
+        if written {
+            let pos = d.scope.position();
+            d.mark(pos);
+        }
+    }
+}
+
+
+#[test]
+fn test_dirty_vec_scope() {
+    use crate::vec_scope::*;
+
+    let vec_scope = VecScope::with_vec(vec!(0u8; 200)).unwrap();
+    let mut dirty_scope = DirtyScope::new(vec_scope);
+
+    let transform =
+        Transform::make_transform(Dirty::new(VecScope::lens()), (60..70usize).chain(130..131), |val| val + 1);
+    transform.transform(&mut dirty_scope);
+
+    assert_eq!(dirty_scope.dirty_count(), 11);
+    assert_eq!(dirty_scope.take_dirty(), vec!(60..70, 130..131));
+    assert_eq!(dirty_scope.take_dirty(), vec!());
+}
+
+#[test]
+fn test_dirty_bit_scopes() {
+    use crate::bit_vec_scope::*;
+    use crate::packed_bit_scope::*;
+
+    let bit_vec_scope = BitVecScope::with_bytes(vec!(0; 2)).unwrap();
+    let mut dirty_scope = DirtyScope::new(bit_vec_scope);
+    let lens = Dirty::new(BitVecScope::lens());
+    for pos in [3usize, 4, 15].iter() {
+        dirty_scope.adjust(*pos);
+        lens.over(&mut dirty_scope, |_| true);
+    }
+    assert_eq!(dirty_scope.take_dirty(), vec!(3..5, 15..16));
+
+    let packed_scope = PackedBitScope::with_words(vec!(0; 4), 4);
+    let mut dirty_scope = DirtyScope::new(packed_scope);
+    let lens = Dirty::new(PackedBitScope::num_lens::<u8>());
+    dirty_scope.adjust(7usize);
+    lens.over(&mut dirty_scope, |_| 0xF);
+    assert_eq!(dirty_scope.scope.bytes, vec!(0, 0, 0, 0xF0));
+    assert_eq!(dirty_scope.take_dirty(), vec!(7..8));
+}
+
+#[test]
+fn test_dirty_prism() {
+    use crate::stream_scope::*;
+    use crate::vec_scope::*;
+
+    // positions where the prism has no focus are not marked
+    let vec_scope = VecScope::with_vec(vec!(Some(1u8), None, Some(3), None)).unwrap();
+    let mut dirty_scope = DirtyScope::new(vec_scope);
+    let transform =
+        Transform::make_transform(Dirty::new(ComposedOptic::new(VecScope::lens(), some_prism())), 0usize..4, |val| val + 1);
+    transform.transform(&mut dirty_scope);
+    assert_eq!(dirty_scope.scope.vec, vec!(Some(2), None, Some(4), None));
+    assert_eq!(dirty_scope.take_dirty(), vec!(0..1, 2..3));
+
+    // an unbounded shape does not size the bitmap
+    let stream_scope = StreamScope::new(std::io::Cursor::new(vec!(0u8; 4)), Vec::new(), 8, 2);
+    let mut dirty_scope = DirtyScope::new(stream_scope);
+    let lens = Dirty::new(StreamScope::num_lens::<u8>());
+    dirty_scope.adjust(2usize);
+    lens.over(&mut dirty_scope, |val| val + 1);
+    assert_eq!(dirty_scope.take_dirty(), vec!(2..3));
+}
@@ -0,0 +1,280 @@
+use std::marker::PhantomData;
+use std::collections::VecDeque;
+
+use myopic::*;
+
+use crate::optic::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit<A> {
+    pub pos: usize,
+    pub old: A,
+    pub new: A,
+}
+
+// History wraps a scope and records every write made through a Recorded
+// optic as an Edit of the element at the cursor. The element lens is used
+// to read elements before and after each write, and to write them back
+// on undo and redo.
+//
+// Edits made between begin and commit form a single undoable step. Edits
+// made outside of a group are each their own step. At most max_edits
+// edits are kept, with the oldest steps dropped first.
+pub struct History<S, O: Lensable> {
+    pub scope: S,
+    pub lens: O,
+    pub max_edits: usize,
+    undo: VecDeque<Vec<Edit<O::Output>>>,
+    redo: Vec<Vec<Edit<O::Output>>>,
+    pending: Option<Vec<Edit<O::Output>>>,
+    num_edits: usize,
+}
+
+impl<S, O, A> History<S, O>
+    where O: Getter + Setter + Lensable<Input=S, Output=A>,
+          S: Position + Scope<usize>,
+          A: Clone + PartialEq {
+    pub fn new(scope: S, lens: O, max_edits: usize) -> History<S, O> {
+        History {
+            scope,
+            lens,
+            max_edits,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: None,
+            num_edits: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scope
+    }
+
+    // wrap an optic on the inner scope so writes through it are recorded.
+    pub fn recorded<U>(optic: U) -> Recorded<U, O> {
+        Recorded {
+            optic,
+            o: PhantomData,
+        }
+    }
+
+    pub fn begin(&mut self) {
+        if self.pending.is_none() {
+            self.pending = Some(Vec::new());
+        }
+    }
+
+    pub fn commit(&mut self) {
+        if let Some(step) = self.pending.take() {
+            self.push_step(step);
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.commit();
+
+        if let Some(step) = self.undo.pop_back() {
+            let pos = self.scope.position();
+            for edit in step.iter().rev() {
+                self.scope.adjust(edit.pos);
+                self.lens.set(&mut self.scope, edit.old.clone());
+            }
+            self.scope.adjust(pos);
+
+            self.num_edits -= step.len();
+            self.redo.push(step);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.commit();
+
+        if let Some(step) = self.redo.pop() {
+            let pos = self.scope.position();
+            for edit in step.iter() {
+                self.scope.adjust(edit.pos);
+                self.lens.set(&mut self.scope, edit.new.clone());
+            }
+            self.scope.adjust(pos);
+
+            self.num_edits += step.len();
+            self.undo.push_back(step);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn transform<F, U, I, Ix>(&mut self, transform: &Transform<F, Recorded<U, O>, Self, A, Ix>)
+        where U: Over + Lensable<Input=S, Output=A>,
+              S: Shape + Scope<I>,
+              F: ActionFn<I, A>,
+              I: Copy,
+              Ix: Clone + IntoIterator<Item=I> {
+        self.begin();
+        transform.transform(self);
+        self.commit();
+    }
+
+    pub fn apply_many<F, U, I, Ix>(&mut self, transforms: Vec<RecordedTransform<F, U, S, O, A, Ix>>)
+        where U: Over + Lensable<Input=S, Output=A>,
+              S: Shape + Scope<I>,
+              F: ActionFn<I, A>,
+              I: Ord + Copy,
+              Ix: Clone + IntoIterator<Item=I> {
+        self.begin();
+        apply_many(transforms, self);
+        self.commit();
+    }
+
+    fn record(&mut self, edit: Edit<A>) {
+        // a new edit invalidates anything that was undone.
+        self.redo.clear();
+
+        match self.pending {
+            Some(ref mut step) => step.push(edit),
+            None => self.push_step(vec!(edit)),
+        }
+    }
+
+    fn push_step(&mut self, step: Vec<Edit<A>>) {
+        if step.is_empty() {
+            return;
+        }
+
+        self.num_edits += step.len();
+        self.undo.push_back(step);
+
+        // always keep the most recent step, even if it is larger than the limit.
+        while self.num_edits > self.max_edits && self.undo.len() > 1 {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.num_edits -= oldest.len();
+            }
+        }
+    }
+}
+
+impl<S: Shape, O: Lensable> Shape for History<S, O> {
+    type Shape = S::Shape;
+
+    fn shape(&self) -> S::Shape {
+        self.scope.shape()
+    }
+}
+
+impl<S: Position, O: Lensable> Position for History<S, O> {
+    fn position(&self) -> usize {
+        self.scope.position()
+    }
+}
+
+impl<I, S: Scope<I>, O: Lensable> Scope<I> for History<S, O> {
+    fn adjust(&mut self, index: I) {
+        self.scope.adjust(index);
+    }
+}
+
+
+// An optic on a History which writes through an optic on the inner scope,
+// recording an edit of the element at the cursor.
+pub struct Recorded<U, O> {
+    pub optic: U,
+    o: PhantomData<O>,
+}
+
+// A transform written through a Recorded optic, as History::apply_many takes.
+pub type RecordedTransform<F, U, S, O, A, Ix> = Transform<F, Recorded<U, O>, History<S, O>, A, Ix>;
+
+impl<U, O> Lensable for Recorded<U, O>
+    where U: Lensable,
+          O: Lensable<Input=U::Input> {
+    type Input = History<U::Input, O>;
+    type Output = U::Output;
+}
+
+impl<U, O, S, A> Over for Recorded<U, O>
+    where U: Over + Lensable<Input=S>,
+          O: Getter + Setter + Lensable<Input=S, Output=A>,
+          S: Position + Scope<usize>,
+          A: Clone + PartialEq {
+    fn over<F>(&self, history: &mut History<S, O>, f: F)
+        where F: FnMut(U::Output) -> U::Output {
+        let old = history.lens.get(&history.scope);
+        self.optic.over(&mut history.scope, f);
+        let new = history.lens.get(&history.scope);
+
+        if old != new {
+            let pos = history.scope.position();
+            history.record(Edit { pos, old, new });
+        }
+    }
+}
+
+
+#[test]
+fn test_history_undo_redo() {
+    use crate::vec_scope::*;
+
+    let vec_scope = VecScope::with_vec(vec!(0u32; 5)).unwrap();
+    let mut history = History::new(vec_scope, VecScope::lens(), 100);
+
+    let transform =
+        Transform::make_transform(History::recorded(VecScope::lens()), (0..5usize).step_by(2), |val| val + 1);
+    history.transform(&transform);
+    assert_eq!(history.scope.vec, vec!(1, 0, 1, 0, 1));
+
+    let lens = History::recorded(VecScope::lens());
+    history.adjust(1usize);
+    lens.over(&mut history, |_| 7);
+    assert_eq!(history.scope.vec, vec!(1, 7, 1, 0, 1));
+
+    assert!(history.undo());
+    assert_eq!(history.scope.vec, vec!(1, 0, 1, 0, 1));
+
+    assert!(history.undo());
+    assert_eq!(history.scope.vec, vec!(0, 0, 0, 0, 0));
+    assert!(!history.undo());
+
+    assert!(history.redo());
+    assert_eq!(history.scope.vec, vec!(1, 0, 1, 0, 1));
+
+    history.adjust(4usize);
+    lens.over(&mut history, |_| 9);
+    assert!(!history.can_redo());
+    assert_eq!(history.scope.vec, vec!(1, 0, 1, 0, 9));
+}
+
+#[test]
+fn test_history_bounded() {
+    use crate::packed_bit_scope::*;
+
+    let packed_scope = PackedBitScope::with_words(vec!(0; 4), 4);
+    let mut history = History::new(packed_scope, PackedBitScope::num_lens::<u8>(), 3);
+    let lens = History::recorded(PackedBitScope::num_lens::<u8>());
+
+    for index in 0..5usize {
+        history.adjust(index);
+        lens.over(&mut history, |_| index as u8 + 1);
+    }
+    assert_eq!(history.scope.bytes, vec!(0x21, 0x43, 0x05, 0x00));
+
+    assert!(history.undo());
+    assert!(history.undo());
+    assert!(history.undo());
+    assert!(!history.undo());
+    assert_eq!(history.scope.bytes, vec!(0x21, 0x00, 0x00, 0x00));
+}
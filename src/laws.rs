@@ -0,0 +1,225 @@
+use std::fmt;
+use std::fmt::Debug;
+
+use myopic::lens::*;
+
+use crate::scope::*;
+use crate::shape::*;
+
+
+/* Lens Laws */
+// A lens over a scope should behave like a field of the element under the
+// cursor:
+//   get-set:  setting the value just read changes nothing
+//   set-get:  reading after a set gives the value set
+//   set-set:  a second set replaces the first, so setting a value and then
+//             the original leaves the scope as it was
+//   locality: a set changes no other position
+// check_lens_laws tests these on clones of a scope for each (position,
+// value) case, comparing the scope through the lens at every position, and
+// returns the first law broken. After setting the value read, and after
+// setting a value and then the original back, the whole scope must also
+// equal the original, which catches a setter that clobbers state the lens
+// never reads, like a neighbouring field. This works for any lens implementing
+// Getter and Setter, the crate's Lens as well as myopic's lenses.
+//
+// Positions past the end are clamped, as adjust does. An empty scope has no
+// positions, so it keeps every law. A scope whose shape is unbounded, like
+// a StreamScope before its end, is read to wherever iter_with stops.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Law {
+    GetSet,
+    SetGet,
+    SetSet,
+    Locality,
+}
+
+// The first counterexample found: after setting value at pos, reading
+// position index gave found where expected was expected. If every read
+// matched but the scope itself changed, unseen holds the expected and
+// found scopes, formatted with Debug, and index is pos.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LawViolation<A> {
+    pub law: Law,
+    pub pos: usize,
+    pub value: A,
+    pub index: usize,
+    pub expected: A,
+    pub found: A,
+    pub unseen: Option<(String, String)>,
+}
+
+impl fmt::Display for Law {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Law::GetSet => write!(f, "get-set"),
+            Law::SetGet => write!(f, "set-get"),
+            Law::SetSet => write!(f, "set-set"),
+            Law::Locality => write!(f, "locality"),
+        }
+    }
+}
+
+impl<A: Debug> fmt::Display for LawViolation<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.unseen {
+            Some((ref expected, ref found)) =>
+                write!(f, "{} law broken setting {:?} at position {}: the scope became {} where {} was expected",
+                       self.law, self.value, self.pos, found, expected),
+            None =>
+                write!(f, "{} law broken setting {:?} at position {}: position {} read {:?}, expected {:?}",
+                       self.law, self.value, self.pos, self.index, self.found, self.expected),
+        }
+    }
+}
+
+pub fn check_lens_laws<L, It>(lens: &L, scope: &L::Input, cases: It) -> Result<(), LawViolation<L::Output>>
+    where L: Getter + Setter,
+          L::Input: Clone + PartialEq + Debug + Scope<usize> + Shape<Shape=usize>,
+          L::Output: Clone + PartialEq,
+          It: IntoIterator<Item=(usize, L::Output)> {
+    let original = view_all(lens, scope);
+    if original.is_empty() {
+        return Ok(());
+    }
+
+    for (pos, value) in cases {
+        let pos = pos.min(original.len() - 1);
+        let violation = |law, index: usize, expected: &L::Output, found: &L::Output, value: &L::Output| {
+            LawViolation {
+                law,
+                pos,
+                value: value.clone(),
+                index,
+                expected: expected.clone(),
+                found: found.clone(),
+                unseen: None,
+            }
+        };
+
+        // the original scope with its cursor at pos
+        let mut at_pos = scope.clone();
+        at_pos.adjust(pos);
+        let unseen = |law, d: &L::Input, value: &L::Output| {
+            LawViolation {
+                unseen: Some((format!("{:?}", at_pos), format!("{:?}", d))),
+                ..violation(law, pos, &original[pos], &original[pos], value)
+            }
+        };
+
+        let mut d = at_pos.clone();
+        let current = lens.get(&d);
+        lens.set(&mut d, current.clone());
+        if let Some((index, found)) = first_difference(&original, &view_all(lens, &d), None) {
+            return Err(violation(Law::GetSet, index, &original[index], &found, &current));
+        }
+        if d != at_pos {
+            return Err(unseen(Law::GetSet, &d, &current));
+        }
+
+        let mut d = at_pos.clone();
+        lens.set(&mut d, value.clone());
+        let found = lens.get(&d);
+        if found != value {
+            return Err(violation(Law::SetGet, pos, &value, &found, &value));
+        }
+
+        if let Some((index, found)) = first_difference(&original, &view_all(lens, &d), Some(pos)) {
+            return Err(violation(Law::Locality, index, &original[index], &found, &value));
+        }
+
+        lens.set(&mut d, current.clone());
+        if let Some((index, found)) = first_difference(&original, &view_all(lens, &d), None) {
+            return Err(violation(Law::SetSet, index, &original[index], &found, &value));
+        }
+        if d != at_pos {
+            return Err(unseen(Law::Locality, &d, &value));
+        }
+    }
+
+    Ok(())
+}
+
+// the focus of the lens at every position
+fn view_all<L>(lens: &L, scope: &L::Input) -> Vec<L::Output>
+    where L: Getter,
+          L::Input: Clone + Scope<usize> + Shape<Shape=usize> {
+    scope.clone().iter_with(lens).collect()
+}
+
+fn first_difference<A: Clone + PartialEq>(expected: &[A], found: &[A], skip: Option<usize>) -> Option<(usize, A)> {
+    expected.iter()
+            .zip(found.iter())
+            .enumerate()
+            .find(|(index, (expected, found))| Some(*index) != skip && expected != found)
+            .map(|(index, (_, found))| (index, found.clone()))
+}
+
+#[test]
+fn test_lens_laws() {
+    use std::rc::Rc;
+    use crate::lens;
+    use crate::packed_bit_scope::*;
+    use crate::vec_scope::*;
+
+    let cases = || (0..40usize).map(|index| ((index * 7) % 13, ((index * 5) % 8) as u8));
+
+    // myopic lenses and the crate's own lenses
+    let vec_scope = VecScope::with_vec((0..13u8).collect()).unwrap();
+    assert_eq!(check_lens_laws(&VecScope::lens(), &vec_scope, cases()), Ok(()));
+
+    let packed_scope = PackedBitScope::from_values((0..13u8).map(|val| val % 8), 3).unwrap();
+    assert_eq!(check_lens_laws(&PackedBitScope::num_lens::<u8>(), &packed_scope, cases()), Ok(()));
+
+    // a value too wide for its field can not be read back
+    let err = check_lens_laws(&PackedBitScope::num_lens::<u8>(), &packed_scope, vec!((4, 9u8))).unwrap_err();
+    assert_eq!((err.law, err.index, err.expected, err.found), (Law::SetGet, 4, 9, 1));
+
+    // a setter that writes its whole byte clobbers the neighbouring field
+    let clobbering: lens::Lens<PackedBitScope, u8> =
+        lens::lens(Rc::new(|packed: &PackedBitScope| (packed.bytes[packed.pos / 2] >> ((packed.pos % 2) * 4)) & 0xF),
+                   Rc::new(|packed: &mut PackedBitScope, n: u8| {
+                       let shift = (packed.pos % 2) * 4;
+                       packed.bytes[packed.pos / 2] = n << shift;
+                   }));
+    let packed_scope = PackedBitScope::with_words(vec!(0x21, 0x43), 4);
+    let err = check_lens_laws(&clobbering, &packed_scope, vec!((2, 3u8))).unwrap_err();
+    assert_eq!(err, LawViolation { law: Law::GetSet, pos: 2, value: 3, index: 3, expected: 4, found: 0, unseen: None });
+    assert_eq!(err.to_string(), "get-set law broken setting 3 at position 2: position 3 read 0, expected 4");
+
+    // one that ignores the value set
+    let ignoring: lens::Lens<PackedBitScope, u8> =
+        lens::lens(Rc::new(|packed: &PackedBitScope| (packed.bytes[packed.pos / 2] >> ((packed.pos % 2) * 4)) & 0xF),
+                   Rc::new(|_: &mut PackedBitScope, _: u8| {}));
+    let err = check_lens_laws(&ignoring, &packed_scope, vec!((1, 2u8), (0, 5))).unwrap_err();
+    assert_eq!((err.law, err.pos, err.found), (Law::SetGet, 0, 1));
+
+    // one that zeroes a neighbouring column the lens never reads
+    let first: lens::Lens<VecScope<(u8, u8)>, u8> =
+        lens::lens(Rc::new(|vec: &VecScope<(u8, u8)>| vec.vec[vec.pos].0),
+                   Rc::new(|vec: &mut VecScope<(u8, u8)>, a: u8| { let pos = vec.pos; vec.vec[pos] = (a, 0) }));
+    let pairs = VecScope::with_vec(vec!((1, 10), (2, 20))).unwrap();
+    let err = check_lens_laws(&first, &pairs, vec!((1, 5u8))).unwrap_err();
+    assert_eq!((err.law, err.pos, err.index), (Law::GetSet, 1, 1));
+    assert_eq!(err.to_string(),
+               "get-set law broken setting 2 at position 1: \
+                the scope became VecScope { vec: [(1, 10), (2, 0)], pos: 1 } \
+                where VecScope { vec: [(1, 10), (2, 20)], pos: 1 } was expected");
+
+    // and one that only does so when the value changes
+    let changing: lens::Lens<VecScope<(u8, u8)>, u8> =
+        lens::lens(Rc::new(|vec: &VecScope<(u8, u8)>| vec.vec[vec.pos].0),
+                   Rc::new(|vec: &mut VecScope<(u8, u8)>, a: u8| {
+                       let pos = vec.pos;
+                       if vec.vec[pos].0 != a {
+                           vec.vec[pos] = (a, 0);
+                       }
+                   }));
+    let err = check_lens_laws(&changing, &pairs, vec!((0, 1u8), (0, 5))).unwrap_err();
+    assert_eq!((err.law, err.pos, err.value), (Law::Locality, 0, 5));
+    assert!(err.unseen.unwrap().1.contains("(1, 0)"));
+
+    // an empty scope has nothing to break
+    let empty_scope = PackedBitScope::with_words(Vec::new(), 4);
+    assert_eq!(check_lens_laws(&ignoring, &empty_scope, vec!((0, 1u8))), Ok(()));
+}
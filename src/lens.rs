@@ -1,5 +1,7 @@
 use std::rc::Rc;
 
+use myopic;
+
 
 pub type Getter<S, A> = dyn Fn(&S) -> A;
 pub type Setter<S, A> = dyn Fn(&mut S, A);
@@ -9,6 +11,15 @@ pub struct Lens<S, A> {
     pub set: Rc<Setter<S, A>>,
 }
 
+impl<S, A> Clone for Lens<S, A> {
+    fn clone(&self) -> Lens<S, A> {
+        Lens {
+            view: self.view.clone(),
+            set: self.set.clone(),
+        }
+    }
+}
+
 pub fn lens<'a, S, A>(getter: Rc<Getter<S, A>>, setter:  Rc<Setter<S, A>>) -> Lens<S, A> {
     Lens {
         view: getter,
@@ -16,6 +27,26 @@ pub fn lens<'a, S, A>(getter: Rc<Getter<S, A>>, setter:  Rc<Setter<S, A>>) -> Le
     }
 }
 
+impl<S, A> myopic::Lensable for Lens<S, A> {
+    type Input = S;
+    type Output = A;
+}
+
+impl<S, A> myopic::Getter for Lens<S, A> {
+    fn get(&self, s: &S) -> A {
+        (self.view)(s)
+    }
+}
+
+impl<S, A> myopic::Setter for Lens<S, A> {
+    fn set(&self, s: &mut S, a: A) {
+        (self.set)(s, a)
+    }
+}
+
+impl<S, A> crate::optic::SliceLens for Lens<S, A> {
+}
+
 pub fn compose<S, A, B>(lhs: Rc<Lens<S, A>>, rhs: Rc<Lens<A, B>>) -> Lens<S, B> 
   where S: 'static, A: 'static, B: 'static {
     let rhs_clone = rhs.clone();
@@ -1,22 +1,58 @@
 extern crate num;
 extern crate bitstream_io;
 
+// derived lenses are myopic lenses, so users get both from here
+pub use myopic;
+pub use scope_derive::Lenses;
+
 pub mod lens;
 pub use crate::lens::*;
+pub mod optic;
+pub use crate::optic::*;
 pub mod scope;
 pub use crate::scope::*;
+pub mod transaction;
+pub use crate::transaction::*;
+pub mod history;
+pub use crate::history::*;
+pub mod dirty;
+pub use crate::dirty::*;
+pub mod spec;
+pub use crate::spec::*;
 pub mod vec_scope;
 pub use crate::vec_scope::*;
 pub mod bit_vec_scope;
 pub use crate::bit_vec_scope::*;
+pub mod rank;
+pub use crate::rank::*;
 pub mod bit_word_scope;
 pub use crate::bit_word_scope::*;
 pub mod packed_bit_scope;
 pub use crate::packed_bit_scope::*;
+pub mod strided_packed_scope;
+pub use crate::strided_packed_scope::*;
+pub mod stream_scope;
+pub use crate::stream_scope::*;
+pub mod paged_scope;
+pub use crate::paged_scope::*;
+pub mod rle_scope;
+pub use crate::rle_scope::*;
+pub mod sparse_scope;
+pub use crate::sparse_scope::*;
+pub mod slice_scope;
+pub use crate::slice_scope::*;
+pub mod roaring_scope;
+pub use crate::roaring_scope::*;
+pub mod diff;
+pub use crate::diff::*;
+pub mod laws;
+pub use crate::laws::*;
 pub mod shape;
 pub use crate::shape::*;
 pub mod types;
 pub use crate::types::*;
+#[cfg(feature = "serde")]
+pub mod serialize;
 
 
 // TODO add benchmarking:
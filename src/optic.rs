@@ -0,0 +1,285 @@
+use std::marker::PhantomData;
+
+use myopic::*;
+
+use crate::scope::*;
+
+
+// An optic that modifies zero or more foci of a D in place. Every lens is an
+// optic with exactly one focus, a prism has zero or one, and a traversal has
+// any number of foci.
+//
+// scope_map and Transform::transform go through over_all and over_each,
+// which optics onto elements stored in a slice override to loop over the
+// slice directly. The defaults do nothing and return false, and the caller
+// falls back to adjusting the cursor and calling over for each index.
+pub trait Over: Lensable {
+    fn over<F>(&self, d: &mut Self::Input, f: F)
+        where F: FnMut(Self::Output) -> Self::Output;
+
+    // apply f to the focus at every position in order, leaving the cursor
+    // on the last, as scope_map does
+    fn over_all<F>(&self, _d: &mut Self::Input, _f: F) -> bool
+        where F: FnMut(usize, Self::Output) -> Self::Output {
+        false
+    }
+
+    // apply f to the focus at each index in turn, leaving the cursor on the
+    // last, as Transform::transform does
+    fn over_each<I, Ix, F>(&self, _d: &mut Self::Input, _indices: Ix, _f: F) -> bool
+        where Self::Input: Scope<I>,
+              I: Copy,
+              Ix: IntoIterator<Item=I>,
+              F: FnMut(I, Self::Output) -> Self::Output {
+        false
+    }
+}
+
+// Lenses made of a Getter and Setter are optics through this blanket impl,
+// which takes over_all and over_each from SliceLens. Lenses implement
+// SliceLens with its defaults unless their foci are stored in a slice.
+pub trait SliceLens: Lensable {
+    fn over_all<F>(&self, _d: &mut Self::Input, _f: F) -> bool
+        where F: FnMut(usize, Self::Output) -> Self::Output {
+        false
+    }
+
+    fn over_each<I, Ix, F>(&self, _d: &mut Self::Input, _indices: Ix, _f: F) -> bool
+        where Self::Input: Scope<I>,
+              I: Copy,
+              Ix: IntoIterator<Item=I>,
+              F: FnMut(I, Self::Output) -> Self::Output {
+        false
+    }
+}
+
+impl<O> Over for O
+    where O: Getter + Setter + SliceLens {
+    fn over<F>(&self, d: &mut O::Input, mut f: F)
+        where F: FnMut(O::Output) -> O::Output {
+        let val = self.get(d);
+        self.set(d, f(val));
+    }
+
+    fn over_all<F>(&self, d: &mut O::Input, f: F) -> bool
+        where F: FnMut(usize, O::Output) -> O::Output {
+        SliceLens::over_all(self, d, f)
+    }
+
+    fn over_each<I, Ix, F>(&self, d: &mut O::Input, indices: Ix, f: F) -> bool
+        where O::Input: Scope<I>,
+              I: Copy,
+              Ix: IntoIterator<Item=I>,
+              F: FnMut(I, O::Output) -> O::Output {
+        SliceLens::over_each(self, d, indices, f)
+    }
+}
+
+impl<G, S, D, A> SliceLens for myopic::lens::lens::Lens<G, S, D, A> {
+}
+
+impl<L1: Lensable, L2: Lensable> SliceLens for myopic::lens::lens::ComposedLens<L1, L2> {
+}
+
+impl<D, A> SliceLens for myopic::lens::lens_box::Lens<D, A> {
+}
+
+impl<D, A, B> SliceLens for myopic::lens::lens_box::ComposedLens<D, A, B> {
+}
+
+impl<D, A> SliceLens for myopic::lens::lens_fn::Lens<D, A> {
+}
+
+impl<O: Lensable, O2: Lensable> SliceLens for myopic::lens::lens_fn::ComposedLens<O, O2> {
+}
+
+
+/* Prism */
+// A prism focuses a value that may not be present, such as the Some of an
+// Option or a single variant of an enum. Actions are skipped when
+// the preview returns None.
+pub struct Prism<P, S, D, A> {
+    pub preview: P,
+    pub setter: S,
+    d: PhantomData<D>,
+    a: PhantomData<A>,
+}
+
+impl<P, S, D, A> Prism<P, S, D, A>
+    where P: Fn(&D) -> Option<A>,
+          S: Fn(&mut D, A) {
+    pub fn new(preview: P, setter: S) -> Self {
+        Prism {
+            preview,
+            setter,
+            d: PhantomData,
+            a: PhantomData,
+        }
+    }
+
+    pub fn preview(&self, d: &D) -> Option<A> {
+        (self.preview)(d)
+    }
+}
+
+impl<P, S, D, A> Lensable for Prism<P, S, D, A> {
+    type Input = D;
+    type Output = A;
+}
+
+impl<P, S, D, A> Over for Prism<P, S, D, A>
+    where P: Fn(&D) -> Option<A>,
+          S: Fn(&mut D, A) {
+    fn over<F>(&self, d: &mut D, mut f: F)
+        where F: FnMut(A) -> A {
+        if let Some(val) = (self.preview)(d) {
+            (self.setter)(d, f(val));
+        }
+    }
+}
+
+// A prism made of plain functions, which can be named in a return type.
+pub type FnPrism<D, A> = Prism<fn(&D) -> Option<A>, fn(&mut D, A), D, A>;
+
+pub fn some_prism<A: Copy>() -> FnPrism<Option<A>, A> {
+    Prism::new(|opt: &Option<A>| *opt,
+               |opt: &mut Option<A>, a: A| *opt = Some(a))
+}
+
+
+/* Traversal */
+// A traversal focuses each of a number of values within a D, such as all
+// fields of a record or all bits of a packed word. The foci are
+// addressed by index from 0 up to the count.
+pub struct Traversal<C, G, S, D, A> {
+    pub count: C,
+    pub getter: G,
+    pub setter: S,
+    d: PhantomData<D>,
+    a: PhantomData<A>,
+}
+
+// A traversal made of plain functions, which can be named in a return type.
+pub type FnTraversal<D, A> = Traversal<fn(&D) -> usize, fn(&D, usize) -> A, fn(&mut D, usize, A), D, A>;
+
+impl<C, G, S, D, A> Traversal<C, G, S, D, A>
+    where C: Fn(&D) -> usize,
+          G: Fn(&D, usize) -> A,
+          S: Fn(&mut D, usize, A) {
+    pub fn new(count: C, getter: G, setter: S) -> Self {
+        Traversal {
+            count,
+            getter,
+            setter,
+            d: PhantomData,
+            a: PhantomData,
+        }
+    }
+
+    pub fn to_vec(&self, d: &D) -> Vec<A> {
+        (0..(self.count)(d)).map(|index| (self.getter)(d, index)).collect()
+    }
+}
+
+impl<C, G, S, D, A> Lensable for Traversal<C, G, S, D, A> {
+    type Input = D;
+    type Output = A;
+}
+
+impl<C, G, S, D, A> Over for Traversal<C, G, S, D, A>
+    where C: Fn(&D) -> usize,
+          G: Fn(&D, usize) -> A,
+          S: Fn(&mut D, usize, A) {
+    fn over<F>(&self, d: &mut D, mut f: F)
+        where F: FnMut(A) -> A {
+        for index in 0..(self.count)(d) {
+            let val = (self.getter)(d, index);
+            (self.setter)(d, index, f(val));
+        }
+    }
+}
+
+
+/* Composition */
+// Composing two optics focuses the foci of the second within each focus of the first.
+pub struct ComposedOptic<O1, O2> {
+    pub lhs: O1,
+    pub rhs: O2,
+}
+
+impl<O1, O2> ComposedOptic<O1, O2> {
+    pub fn new(lhs: O1, rhs: O2) -> Self {
+        ComposedOptic {
+            lhs,
+            rhs,
+        }
+    }
+}
+
+impl<O1, O2> Lensable for ComposedOptic<O1, O2>
+    where O1: Lensable,
+          O2: Lensable {
+    type Input = O1::Input;
+    type Output = O2::Output;
+}
+
+impl<O1, O2> Over for ComposedOptic<O1, O2>
+    where O1: Over,
+          O2: Over<Input=O1::Output> {
+    fn over<F>(&self, d: &mut O1::Input, mut f: F)
+        where F: FnMut(O2::Output) -> O2::Output {
+        self.lhs.over(d, |mut a| {
+            self.rhs.over(&mut a, &mut f);
+            a
+        });
+    }
+}
+
+
+#[test]
+fn test_prism_option() {
+    let prism = some_prism::<u32>();
+
+    let mut opt = Some(1);
+    prism.over(&mut opt, |a| a + 1);
+    assert_eq!(opt, Some(2));
+
+    let mut opt = None;
+    prism.over(&mut opt, |a| a + 1);
+    assert_eq!(opt, None);
+}
+
+#[test]
+fn test_traversal_fields() {
+    let traversal =
+        Traversal::new(|_: &(u8, u8, u8)| 3,
+                       |rec: &(u8, u8, u8), index| [rec.0, rec.1, rec.2][index],
+                       |rec: &mut (u8, u8, u8), index, a| match index {
+                           0 => rec.0 = a,
+                           1 => rec.1 = a,
+                           _ => rec.2 = a,
+                       });
+
+    let mut rec = (1, 2, 3);
+    traversal.over(&mut rec, |a| a * 10);
+    assert_eq!(rec, (10, 20, 30));
+    assert_eq!(traversal.to_vec(&rec), vec!(10, 20, 30));
+}
+
+#[test]
+fn test_composed_prism_traversal() {
+    let pair_traversal =
+        Traversal::new(|_: &(u8, u8)| 2,
+                       |pair: &(u8, u8), index| if index == 0 { pair.0 } else { pair.1 },
+                       |pair: &mut (u8, u8), index, a| if index == 0 { pair.0 = a } else { pair.1 = a });
+
+    let optic = ComposedOptic::new(some_prism::<(u8, u8)>(), pair_traversal);
+
+    let mut opt = Some((1, 2));
+    optic.over(&mut opt, |a| a + 1);
+    assert_eq!(opt, Some((2, 3)));
+
+    let mut opt = None;
+    optic.over(&mut opt, |a| a + 1);
+    assert_eq!(opt, None);
+}
@@ -1,18 +1,23 @@
 use std::rc::Rc;
-use std::cmp;
+use std::iter::FromIterator;
+use std::mem;
 
 use num::clamp;
 
-use num::zero;
 use num::PrimInt;
 use num::cast::NumCast;
 
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
 use crate::lens::*;
+use crate::optic::*;
 use crate::shape::*;
 use crate::scope::*;
 
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct PackedBitScope {
     pub bytes: Vec<u8>,
     pub pos: usize,
@@ -37,6 +42,21 @@ impl PackedBitScope {
     }
 }
 
+// one bit fields
+impl FromIterator<bool> for PackedBitScope {
+    fn from_iter<It: IntoIterator<Item=bool>>(iter: It) -> PackedBitScope {
+        let bytes = crate::bit_vec_scope::pack_bits(iter);
+        assert!(!bytes.is_empty(), "PackedBitScope needs at least one bit");
+        PackedBitScope::with_words(bytes, 1)
+    }
+}
+
+impl Position for PackedBitScope {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
 impl Scope<usize> for PackedBitScope {
     fn adjust(&mut self, pos: usize) {
         let max_pos = (self.bytes.len() * 8) / self.bits_used;
@@ -51,6 +71,8 @@ impl Scope<isize> for PackedBitScope {
     }
 }
 
+crate::impl_typed_indices!([] PackedBitScope);
+
 impl PackedBitScope {
     pub fn bit_lens() -> Lens<PackedBitScope, bool> {
         lens(Rc::new(|bytes: &PackedBitScope| get_packedbit_scope_bits(bytes)),
@@ -62,6 +84,12 @@ impl PackedBitScope {
              Rc::new(|bytes: &mut PackedBitScope, n: N| set_packedbit_scope_num(bytes, n)))
     }
 
+    pub fn bits_traversal() -> FnTraversal<PackedBitScope, bool> {
+        Traversal::new(|bytes: &PackedBitScope| bytes.bits_used,
+                       |bytes: &PackedBitScope, bit: usize| get_packedbit_scope_bit_at(bytes, bit),
+                       |bytes: &mut PackedBitScope, bit: usize, a: bool| set_packedbit_scope_bit_at(bytes, bit, a))
+    }
+
     /*
     pub fn stream_lens() -> Lens<PackedBitScope, BitReader<Cursor<&[u8]>, BigEndian>> {
         lens(Rc::new(|bytes: &PackedBitScope| get_packedbit_scope_stream(bytes)),
@@ -71,6 +99,165 @@ impl PackedBitScope {
     */
 }
 
+// A value that does not fit in the field width it is being packed into.
+// Values of signed types are packed as two's complement fields, so in 4
+// bits they range from -8 to 7, and unsigned values from 0 to 15. value
+// holds the low 64 bits of the value in two's complement.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PackOverflow {
+    pub index: usize,
+    pub value: u64,
+    pub bits_used: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PackError {
+    // fields are between 1 and 64 bits wide
+    Width { bits_used: usize },
+    Overflow(PackOverflow),
+}
+
+impl PackedBitScope {
+    pub fn from_values<N, It>(values: It, bits_used: usize) -> Result<PackedBitScope, PackError>
+        where N: PrimInt,
+              It: IntoIterator<Item=N> {
+        if bits_used == 0 || bits_used > 64 {
+            return Err(PackError::Width { bits_used });
+        }
+
+        let mut overflow = None;
+        let raw_values = values.into_iter().enumerate().map_while(|(index, n)| {
+            let raw = field_bits(n, bits_used);
+            if raw.is_none() {
+                overflow = Some(PackOverflow { index, value: low_bits(n), bits_used });
+            }
+            raw
+        });
+        let bytes = pack_values(raw_values, bits_used)?;
+
+        match overflow {
+            Some(overflow) => Err(PackError::Overflow(overflow)),
+            None => Ok(PackedBitScope::with_words(bytes, bits_used)),
+        }
+    }
+
+    // every field in order, as raw bits
+    pub fn values(&self) -> PackedValues<'_> {
+        PackedValues {
+            bytes: &self.bytes,
+            bits_used: self.bits_used,
+            index: 0,
+            acc: 0,
+            acc_bits: 0,
+            remaining: self.shape(),
+        }
+    }
+
+    // every field as an N, sign extended for signed N. None if a field
+    // does not fit in N.
+    pub fn to_values<N: PrimInt>(&self) -> Option<Vec<N>> {
+        self.values()
+            .map(|raw| field_value(raw, self.bits_used))
+            .collect()
+    }
+
+    // re-encode every field with a new width, reading each as an N so
+    // signed fields keep their sign. If a field does not fit in N or in the
+    // new width, the scope is left unchanged and the first such field is
+    // reported.
+    pub fn repack<N: PrimInt>(&mut self, new_bits: usize) -> Result<(), PackError> {
+        let bits_used = self.bits_used;
+        let mut overflow = None;
+        let values = self.values().enumerate().map_while(|(index, raw)| {
+            let n = field_value::<N>(raw, bits_used);
+            if n.is_none() {
+                overflow = Some(PackOverflow { index, value: raw, bits_used: mem::size_of::<N>() * 8 });
+            }
+            n
+        });
+        let repacked = PackedBitScope::from_values(values, new_bits);
+        if let Some(overflow) = overflow {
+            return Err(PackError::Overflow(overflow));
+        }
+
+        self.bytes = repacked?.bytes;
+        self.bits_used = new_bits;
+        let pos = self.pos;
+        self.adjust(pos);
+        Ok(())
+    }
+}
+
+// pack raw values least significant bit first, the layout the lenses use
+fn pack_values<It: Iterator<Item=u64>>(values: It, bits_used: usize) -> Result<Vec<u8>, PackError> {
+    if bits_used == 0 || bits_used > 64 {
+        return Err(PackError::Width { bits_used });
+    }
+
+    let mut bytes = Vec::with_capacity(((values.size_hint().0 * bits_used) / 8) + 1);
+    let mut acc: u128 = 0;
+    let mut acc_bits = 0;
+
+    for (index, raw) in values.enumerate() {
+        if bits_used < 64 && (raw >> bits_used) != 0 {
+            return Err(PackError::Overflow(PackOverflow {
+                index,
+                value: raw,
+                bits_used,
+            }));
+        }
+
+        acc |= (raw as u128) << acc_bits;
+        acc_bits += bits_used;
+        while acc_bits >= 8 {
+            bytes.push(acc as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
+    }
+
+    if acc_bits > 0 {
+        bytes.push(acc as u8);
+    }
+
+    Ok(bytes)
+}
+
+pub struct PackedValues<'a> {
+    bytes: &'a [u8],
+    bits_used: usize,
+    index: usize,
+    acc: u128,
+    acc_bits: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for PackedValues<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        while self.acc_bits < self.bits_used {
+            self.acc |= (self.bytes[self.index] as u128) << self.acc_bits;
+            self.index += 1;
+            self.acc_bits += 8;
+        }
+
+        let raw = (self.acc & ((1u128 << self.bits_used) - 1)) as u64;
+        self.acc >>= self.bits_used;
+        self.acc_bits -= self.bits_used;
+        self.remaining -= 1;
+        Some(raw)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
 pub fn get_packedbit_scope_bits(packedbit_scope: &PackedBitScope) -> bool {
     let bit_pos = packedbit_scope.pos * packedbit_scope.bits_used;
     let index = bit_pos / 8;
@@ -89,77 +276,109 @@ pub fn set_packedbit_scope_bits(packedbit_scope: &mut PackedBitScope, a: bool) {
     packedbit_scope.bytes[index] = loc_set;
 }
 
-pub fn get_packedbit_scope_num<N: PrimInt>(packedbit_scope: &PackedBitScope) -> N {
-    let bit_pos = packedbit_scope.pos * packedbit_scope.bits_used;
-    let mut index = bit_pos / 8;
-    let bit_index = (bit_pos % 8) as u32;
+pub fn get_packedbit_scope_bit_at(packedbit_scope: &PackedBitScope, bit: usize) -> bool {
+    let bit_pos = (packedbit_scope.pos * packedbit_scope.bits_used) + bit;
+    (packedbit_scope.bytes[bit_pos / 8] & (1 << (bit_pos % 8))) != 0
+}
 
-    let mut bits_left = packedbit_scope.bits_used;
-    let mut bits_found = 0;
+pub fn set_packedbit_scope_bit_at(packedbit_scope: &mut PackedBitScope, bit: usize, a: bool) {
+    let bit_pos = (packedbit_scope.pos * packedbit_scope.bits_used) + bit;
+    let index = bit_pos / 8;
+    let bit_index = bit_pos % 8;
+    packedbit_scope.bytes[index] =
+        (packedbit_scope.bytes[index] & !(1 << bit_index)) | ((a as u8) << bit_index);
+}
+
+fn is_signed<N: PrimInt>() -> bool {
+    N::min_value() < N::zero()
+}
+
+// the two's complement value of a field's raw bits
+fn sign_extend(raw: u64, bits_used: usize) -> i64 {
+    let shift = 64 - bits_used;
+    ((raw << shift) as i64) >> shift
+}
 
-    let mut n: N = zero();
+// the raw bits of n as a field, or None if n does not fit in bits_used
+// bits. Signed types are stored in two's complement.
+pub(crate) fn field_bits<N: PrimInt>(n: N, bits_used: usize) -> Option<u64> {
+    let mask = u64::MAX >> (64 - bits_used);
+    if is_signed::<N>() {
+        let n = n.to_i64()?;
+        let raw = (n as u64) & mask;
+        if sign_extend(raw, bits_used) == n {
+            Some(raw)
+        } else {
+            None
+        }
+    } else {
+        n.to_u64().filter(|raw| raw & !mask == 0)
+    }
+}
 
-    // first bits
-    if bit_index != 0 {
-        let bits: N = NumCast::from(packedbit_scope.bytes[index] >> bit_index).unwrap();
-        bits_found += cmp::min(8 - bit_index as usize, packedbit_scope.bits_used);
-        n = n | (bits & NumCast::from(2u32.pow(bits_found as u32) - 1).unwrap());
-        bits_left -= bits_found;
-        index += 1;
+// a field's raw bits as an N, sign extended for signed N. None if the
+// value does not fit in N.
+pub(crate) fn field_value<N: PrimInt>(raw: u64, bits_used: usize) -> Option<N> {
+    if is_signed::<N>() {
+        NumCast::from(sign_extend(raw, bits_used))
+    } else {
+        NumCast::from(raw)
     }
+}
 
-    // middle bits
-    while bits_left >= 8 {
-        let bits: N = NumCast::from(packedbit_scope.bytes[index]).unwrap();
-        n = n | (bits << bits_found);
-        bits_found += 8;
-        bits_left -= 8;
-        index += 1;
+// the low 64 bits of n in two's complement, which hold every bit of a
+// field however wide n is
+pub(crate) fn low_bits<N: PrimInt>(n: N) -> u64 {
+    match n.to_u64().or_else(|| n.to_i64().map(|n| n as u64)) {
+        Some(raw) => raw,
+        None => NumCast::from(n & NumCast::from(u64::MAX).unwrap()).unwrap(),
     }
+}
+
+// read width bits starting at bit_pos
+pub(crate) fn get_bits(bytes: &[u8], bit_pos: usize, width: usize) -> u64 {
+    let first = bit_pos / 8;
+    let last = (bit_pos + width - 1) / 8;
 
-    // final bits
-    if bits_left > 0 {
-        let bits = packedbit_scope.bytes[index] & (2u8.pow(bits_left as u32) - 1);
-        let bits: N = NumCast::from(bits).unwrap();
-        n = n | (bits << bits_found);
+    let mut acc: u128 = 0;
+    for (index, byte) in bytes[first..=last].iter().enumerate() {
+        acc |= (*byte as u128) << (index * 8);
     }
 
-    return n;
+    ((acc >> (bit_pos % 8)) & ((1u128 << width) - 1)) as u64
 }
 
-pub fn set_packedbit_scope_num<N: PrimInt>(packedbit_scope: &mut PackedBitScope, n: N) {
-    let bit_pos = packedbit_scope.pos * packedbit_scope.bits_used;
-    let mut index = bit_pos / 8;
-    let bit_index = (bit_pos % 8) as u32;
+// write the low width bits of raw starting at bit_pos
+pub(crate) fn set_bits(bytes: &mut [u8], bit_pos: usize, width: usize, raw: u64) {
+    let mut bit_pos = bit_pos;
+    let mut raw = raw;
+    let mut bits_left = width;
 
-    let mut bits_left = packedbit_scope.bits_used;
-    let mut bits_used = 0;
+    while bits_left > 0 {
+        let bit_index = bit_pos % 8;
+        let bits = (8 - bit_index).min(bits_left);
+        let mask = (((1u16 << bits) - 1) as u8) << bit_index;
 
-    // first byte
-    if bit_index != 0 {
-        let mut first_byte: u8 =
-            packedbit_scope.bytes[index] as u8 & (2u8.pow(bit_index) - 1);
-        first_byte |= n.to_u8().unwrap() << bit_index as usize;
-        packedbit_scope.bytes[index] = first_byte;
+        let byte = &mut bytes[bit_pos / 8];
+        *byte = (*byte & !mask) | (((raw as u8) << bit_index) & mask);
 
-        bits_used = cmp::min(8 - bit_index as usize, packedbit_scope.bits_used);
-        bits_left -= bits_used;
-        index += 1;
+        raw >>= bits;
+        bit_pos += bits;
+        bits_left -= bits;
     }
+}
 
-    // middle bytes
-    for _ in 0..(bits_left / 8) {
-        packedbit_scope.bytes[index] = (n >> bits_used).to_u8().unwrap();
-        index += 1;
-        bits_used += 8;
-        bits_left -= 8;
-    }
+pub fn get_packedbit_scope_num<N: PrimInt>(packedbit_scope: &PackedBitScope) -> N {
+    let bits_used = packedbit_scope.bits_used;
+    let raw = get_bits(&packedbit_scope.bytes, packedbit_scope.pos * bits_used, bits_used);
+    field_value(raw, bits_used).expect("packed value does not fit in the lens type")
+}
 
-    // last byte
-    if bits_left > 0 {
-        let high_bits = packedbit_scope.bytes[index] & !(2u8.pow(bits_left as u32) - 1);
-        packedbit_scope.bytes[index] = high_bits | (n >> bits_used).to_u8().unwrap();
-    }
+pub fn set_packedbit_scope_num<N: PrimInt>(packedbit_scope: &mut PackedBitScope, n: N) {
+    // work on the two's complement bits of n so signed values pack as well
+    let bits_used = packedbit_scope.bits_used;
+    let bit_pos = packedbit_scope.pos * bits_used;
+    set_bits(&mut packedbit_scope.bytes, bit_pos, bits_used, low_bits(n));
 }
 
 #[test]
@@ -207,6 +426,120 @@ fn test_packedbit_scope_set_9() {
     assert_eq!((packed_lens.view)(&packed_scope), 0xA5);
 }
 
+#[test]
+fn test_packedbit_scope_set_neighbours() {
+    let mut packed_scope = PackedBitScope::with_words(vec!(0xFF; 4), 3);
+    let packed_lens = PackedBitScope::num_lens::<u8>();
+
+    packed_scope.adjust(1usize);
+    (packed_lens.set)(&mut packed_scope, 0);
+    assert_eq!(packed_scope.bytes, vec!(0xC7, 0xFF, 0xFF, 0xFF));
+
+    let mut packed_scope = PackedBitScope::with_words(vec!(0; 4), 9);
+    let packed_lens = PackedBitScope::num_lens::<u16>();
+
+    packed_scope.adjust(1usize);
+    (packed_lens.set)(&mut packed_scope, 0x1FF);
+    assert_eq!((packed_lens.view)(&packed_scope), 0x1FF);
+    assert_eq!(packed_scope.bytes, vec!(0x00, 0xFE, 0x03, 0x00));
+
+    // values wider than 64 bits keep their low bits
+    let packed_lens = PackedBitScope::num_lens::<u128>();
+    packed_scope.adjust(0usize);
+    (packed_lens.set)(&mut packed_scope, (1u128 << 100) | 0x0AA);
+    assert_eq!(packed_scope.bytes, vec!(0xAA, 0xFE, 0x03, 0x00));
+    let packed_lens = PackedBitScope::num_lens::<i128>();
+    (packed_lens.set)(&mut packed_scope, i128::MIN | 0x055);
+    assert_eq!(packed_scope.bytes, vec!(0x55, 0xFE, 0x03, 0x00));
+}
+
+#[test]
+fn test_packedbit_scope_bits_traversal() {
+    let mut packed_scope = PackedBitScope::with_words(vec!(0x0F, 0x00), 4);
+    let traversal = PackedBitScope::bits_traversal();
+
+    assert_eq!(traversal.to_vec(&packed_scope), vec!(true, true, true, true));
+
+    packed_scope.adjust(1isize);
+    traversal.over(&mut packed_scope, |bit| !bit);
+    assert_eq!(packed_scope.bytes, vec!(0xFF, 0x00));
+}
+
+#[test]
+fn test_packedbit_scope_repack() {
+    let counters: Vec<u16> = vec!(0, 1, 300, 511, 17, 256, 2, 9);
+    let mut packed_scope = PackedBitScope::from_values(counters.clone(), 9).unwrap();
+    assert_eq!(packed_scope.bytes.len(), 9);
+    assert_eq!(packed_scope.to_values::<u16>(), Some(counters.clone()));
+
+    let packed_lens = PackedBitScope::num_lens::<u16>();
+    packed_scope.adjust(3usize);
+    assert_eq!((packed_lens.view)(&packed_scope), 511);
+
+    // growing keeps every value and the cursor
+    packed_scope.repack::<u16>(12).unwrap();
+    assert_eq!(packed_scope.bytes.len(), 12);
+    assert_eq!(packed_scope.to_values::<u16>(), Some(counters.clone()));
+    assert_eq!((packed_lens.view)(&packed_scope), 511);
+    (packed_lens.set)(&mut packed_scope, 4000);
+    assert_eq!(packed_scope.values().nth(3), Some(4000));
+
+    // shrinking below the largest value fails and leaves the scope alone
+    let before = packed_scope.clone();
+    assert_eq!(packed_scope.repack::<u16>(8),
+               Err(PackError::Overflow(PackOverflow { index: 2, value: 300, bits_used: 8 })));
+    assert_eq!(packed_scope.repack::<u8>(16),
+               Err(PackError::Overflow(PackOverflow { index: 2, value: 300, bits_used: 8 })));
+    assert_eq!(packed_scope.repack::<u16>(0), Err(PackError::Width { bits_used: 0 }));
+    assert!(packed_scope == before);
+
+    // signed fields keep their sign growing and shrinking
+    let mut signed = PackedBitScope::from_values(vec!(-1i8, 7, -8, 0), 4).unwrap();
+    signed.repack::<i8>(8).unwrap();
+    assert_eq!(signed.bytes, vec!(0xFF, 0x07, 0xF8, 0x00));
+    assert_eq!(signed.to_values::<i8>(), Some(vec!(-1, 7, -8, 0)));
+    signed.repack::<i8>(4).unwrap();
+    assert_eq!(signed.bytes, vec!(0x7F, 0x08));
+    assert_eq!(signed.repack::<i8>(3),
+               Err(PackError::Overflow(PackOverflow { index: 1, value: 7, bits_used: 3 })));
+
+    let small = PackedBitScope::from_values(vec!(1u8, 2, 3), 2).unwrap();
+    assert_eq!(small.bytes, vec!(0x39));
+    assert_eq!(PackedBitScope::from_values(vec!(1u8, 4), 2).err(),
+               Some(PackError::Overflow(PackOverflow { index: 1, value: 4, bits_used: 2 })));
+    assert_eq!(PackedBitScope::from_values(vec!(1u8), 65).err(), Some(PackError::Width { bits_used: 65 }));
+
+    let wide = PackedBitScope::from_values(vec!(u64::MAX, 5), 64).unwrap();
+    assert_eq!(wide.to_values::<u64>(), Some(vec!(u64::MAX, 5)));
+}
+
+#[test]
+fn test_packedbit_scope_signed_values() {
+    // signed values are two's complement fields, sign extended when read
+    let signed = PackedBitScope::from_values(vec!(-1i8, 7, -8, 0), 4).unwrap();
+    assert_eq!(signed.bytes, vec!(0x7F, 0x08));
+    assert_eq!(signed.to_values::<i8>(), Some(vec!(-1, 7, -8, 0)));
+    assert_eq!(signed.to_values::<i64>(), Some(vec!(-1, 7, -8, 0)));
+    assert_eq!(signed.to_values::<u8>(), Some(vec!(15, 7, 8, 0)));
+
+    assert_eq!(PackedBitScope::from_values(vec!(-1i8, 8), 4).err(),
+               Some(PackError::Overflow(PackOverflow { index: 1, value: 8, bits_used: 4 })));
+    assert_eq!(PackedBitScope::from_values(vec!(-9i16), 4).err(),
+               Some(PackError::Overflow(PackOverflow { index: 0, value: (-9i64) as u64, bits_used: 4 })));
+    let min = PackedBitScope::from_values(vec!(i64::MIN, i64::MAX), 64).unwrap();
+    assert_eq!(min.to_values::<i64>(), Some(vec!(i64::MIN, i64::MAX)));
+
+    // a field too large for the requested type
+    let large = PackedBitScope::from_values(vec!(200u16, 1), 9).unwrap();
+    assert_eq!(large.to_values::<i8>(), None);
+    assert_eq!(large.to_values::<u8>(), Some(vec!(200, 1)));
+    assert_eq!(PackedBitScope::from_values(vec!(200u8), 8).unwrap().to_values::<i8>(), Some(vec!(-56)));
+
+    // values wider than 64 bits report their low bits
+    assert_eq!(PackedBitScope::from_values(vec!((1u128 << 64) | 3), 8).err(),
+               Some(PackError::Overflow(PackOverflow { index: 0, value: 3, bits_used: 8 })));
+}
+
 /*
 // TODO consider lens for arbitrary data, providing a bitstream interface for encoding/decoding
 pub fn get_packedbit_scope_stream<R: Read, N: Numeric>(packedbit_scope: &PackedBitScope) -> BitReader<Cursor<&[u8]>, BigEndian> {
@@ -0,0 +1,426 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use num::clamp;
+use num::PrimInt;
+
+use crate::lens::*;
+use crate::scope::*;
+use crate::shape::*;
+use crate::packed_bit_scope::{get_bits, set_bits, field_value, low_bits};
+
+
+/* Paged Scope */
+// A PagedScope is a packed field scope, laid out like a PackedBitScope,
+// over any Read + Write + Seek storage. Storage is read in fixed-size pages
+// kept in a least recently used cache of a fixed number of pages. Written
+// pages are marked dirty and written back when evicted or on flush. With
+// bits_used a multiple of 8, fields are little endian elements.
+//
+// Reading through a lens loads pages, so the cache sits behind a RefCell.
+// Lenses can not return I/O errors, so the first one is kept and returned
+// by flush, and reads after it give zeros. A page which fails to write back
+// stays cached and dirty, so a later flush tries it again.
+//
+// Like a BufWriter, dropping the scope writes back dirty pages and ignores
+// any errors. Call flush or into_inner to see them.
+pub struct PagedScope<F: Read + Write + Seek> {
+    cache: RefCell<PageCache<F>>,
+    len: usize,
+    pos: usize,
+    bits_used: usize,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+struct Page {
+    bytes: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+struct PageCache<F: Read + Write + Seek> {
+    // only taken by into_inner
+    file: Option<F>,
+    pages: HashMap<usize, Page>,
+    page_size: usize,
+    capacity: usize,
+    len: usize,
+    tick: u64,
+    error: Option<io::Error>,
+}
+
+impl<F: Read + Write + Seek> PageCache<F> {
+    // the page, loading it and evicting the least recently used page if
+    // needed. Returns whether it was already cached.
+    fn page(&mut self, index: usize) -> io::Result<(&mut Page, bool)> {
+        self.tick += 1;
+        let hit = self.pages.contains_key(&index);
+
+        if !hit {
+            if self.pages.len() >= self.capacity {
+                self.evict()?;
+            }
+
+            let start = index * self.page_size;
+            let mut bytes = vec!(0; self.page_size.min(self.len - start));
+            let file = self.file.as_mut().unwrap();
+            file.seek(SeekFrom::Start(start as u64))?;
+            file.read_exact(&mut bytes)?;
+            self.pages.insert(index, Page { bytes, dirty: false, last_used: 0 });
+        }
+
+        let page = self.pages.get_mut(&index).unwrap();
+        page.last_used = self.tick;
+        Ok((page, hit))
+    }
+
+    fn evict(&mut self) -> io::Result<()> {
+        let index = match self.pages.iter().min_by_key(|(_, page)| page.last_used) {
+            Some((index, _)) => *index,
+            None => return Ok(()),
+        };
+
+        // the page is only dropped once it is written
+        if self.pages[&index].dirty {
+            self.write_page(index)?;
+        }
+        self.pages.remove(&index);
+        Ok(())
+    }
+
+    fn write_page(&mut self, index: usize) -> io::Result<()> {
+        let file = self.file.as_mut().unwrap();
+        let page = self.pages.get_mut(&index).unwrap();
+        file.seek(SeekFrom::Start((index * self.page_size) as u64))?;
+        file.write_all(&page.bytes)?;
+        page.dirty = false;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<usize> =
+            self.pages.iter().filter(|(_, page)| page.dirty).map(|(index, _)| *index).collect();
+        dirty.sort();
+
+        for index in dirty {
+            self.write_page(index)?;
+        }
+        self.file.as_mut().unwrap().flush()
+    }
+}
+
+impl<F: Read + Write + Seek> Drop for PageCache<F> {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
+
+impl<F: Read + Write + Seek> PagedScope<F> {
+    pub fn new(mut file: F, page_size: usize, capacity: usize, bits_used: usize) -> io::Result<PagedScope<F>> {
+        assert!(page_size > 0 && capacity > 0, "page_size and capacity must be nonzero");
+        assert!(bits_used > 0 && bits_used <= 64, "bits_used must be between 1 and 64");
+
+        let len = file.seek(SeekFrom::End(0))? as usize;
+        if (len * 8) < bits_used {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "storage holds no whole field"));
+        }
+
+        Ok(PagedScope {
+            cache: RefCell::new(PageCache {
+                file: Some(file),
+                pages: HashMap::new(),
+                page_size,
+                capacity,
+                len,
+                tick: 0,
+                error: None,
+            }),
+            len,
+            pos: 0,
+            bits_used,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        })
+    }
+
+    pub fn num_lens<N: PrimInt>() -> Lens<PagedScope<F>, N> {
+        lens(Rc::new(|paged: &PagedScope<F>| get_paged_scope_num(paged)),
+             Rc::new(|paged: &mut PagedScope<F>, n: N| set_paged_scope_num(paged, n)))
+    }
+
+    // the lowest bit of each field
+    pub fn bit_lens() -> Lens<PagedScope<F>, bool> {
+        lens(Rc::new(|paged: &PagedScope<F>| get_paged_scope_num::<F, u64>(paged) & 1 != 0),
+             Rc::new(|paged: &mut PagedScope<F>, a: bool| {
+                 let raw: u64 = get_paged_scope_num(paged);
+                 set_paged_scope_num(paged, (raw & !1) | a as u64)
+             }))
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.get()
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.get()
+    }
+
+    pub fn reset_counters(&self) {
+        self.hits.set(0);
+        self.misses.set(0);
+    }
+
+    // write back every dirty page, returning the first error seen since
+    // the last flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let cache = self.cache.get_mut();
+        if let Some(error) = cache.error.take() {
+            return Err(error);
+        }
+        cache.flush()
+    }
+
+    pub fn into_inner(mut self) -> io::Result<F> {
+        self.flush()?;
+        Ok(self.cache.into_inner().file.take().unwrap())
+    }
+
+    // copy the bytes starting at start into bytes
+    fn read_bytes(&self, start: usize, bytes: &mut [u8]) {
+        let mut cache = self.cache.borrow_mut();
+        let page_size = cache.page_size;
+
+        let mut done = 0;
+        while done < bytes.len() && cache.error.is_none() {
+            let byte = start + done;
+            match cache.page(byte / page_size) {
+                Ok((page, hit)) => {
+                    self.count(hit);
+                    let offset = byte % page_size;
+                    let count = (page.bytes.len() - offset).min(bytes.len() - done);
+                    bytes[done..(done + count)].copy_from_slice(&page.bytes[offset..(offset + count)]);
+                    done += count;
+                },
+                Err(error) => cache.error = Some(error),
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, start: usize, bytes: &[u8]) {
+        let mut cache = self.cache.borrow_mut();
+        let page_size = cache.page_size;
+
+        let mut done = 0;
+        while done < bytes.len() && cache.error.is_none() {
+            let byte = start + done;
+            match cache.page(byte / page_size) {
+                Ok((page, hit)) => {
+                    self.count(hit);
+                    let offset = byte % page_size;
+                    let count = (page.bytes.len() - offset).min(bytes.len() - done);
+                    page.bytes[offset..(offset + count)].copy_from_slice(&bytes[done..(done + count)]);
+                    page.dirty = true;
+                    done += count;
+                },
+                Err(error) => cache.error = Some(error),
+            }
+        }
+    }
+
+    fn count(&self, hit: bool) {
+        if hit {
+            self.hits.set(self.hits.get() + 1);
+        } else {
+            self.misses.set(self.misses.get() + 1);
+        }
+    }
+
+    // the bytes holding the field under the cursor, and the bit offset of
+    // the field within them
+    fn field_bytes(&self) -> (usize, usize, usize) {
+        let bit_pos = self.pos * self.bits_used;
+        let first = bit_pos / 8;
+        let last = (bit_pos + self.bits_used - 1) / 8;
+        (first, (last - first) + 1, bit_pos % 8)
+    }
+}
+
+impl<F: Read + Write + Seek> Shape for PagedScope<F> {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        (self.len * 8) / self.bits_used
+    }
+}
+
+impl<F: Read + Write + Seek> Position for PagedScope<F> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+pub fn get_paged_scope_num<F: Read + Write + Seek, N: PrimInt>(paged_scope: &PagedScope<F>) -> N {
+    let (start, count, bit_index) = paged_scope.field_bytes();
+    let mut bytes = [0u8; 9];
+    paged_scope.read_bytes(start, &mut bytes[..count]);
+    field_value(get_bits(&bytes, bit_index, paged_scope.bits_used), paged_scope.bits_used)
+        .expect("packed value does not fit in the lens type")
+}
+
+pub fn set_paged_scope_num<F: Read + Write + Seek, N: PrimInt>(paged_scope: &mut PagedScope<F>, n: N) {
+    let (start, count, bit_index) = paged_scope.field_bytes();
+    let mut bytes = [0u8; 9];
+    paged_scope.read_bytes(start, &mut bytes[..count]);
+    set_bits(&mut bytes, bit_index, paged_scope.bits_used, low_bits(n));
+    paged_scope.write_bytes(start, &bytes[..count]);
+}
+
+impl<F: Read + Write + Seek> Scope<usize> for PagedScope<F> {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.shape() - 1);
+    }
+}
+
+impl<F: Read + Write + Seek> Scope<isize> for PagedScope<F> {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.shape() - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!([F: Read + Write + Seek] PagedScope<F>);
+
+#[test]
+fn test_paged_scope() {
+    use std::io::Cursor;
+
+    let input: Vec<u8> = (0..4096u32).map(|index| (index * 13) as u8).collect();
+    let mut paged_scope = PagedScope::new(Cursor::new(input.clone()), 256, 4, 32).unwrap();
+    let lens = PagedScope::num_lens::<u32>();
+
+    assert_eq!(paged_scope.shape(), 1024);
+    paged_scope.adjust(100usize);
+    assert_eq!((lens.view)(&paged_scope), u32::from_le_bytes([0x50, 0x5D, 0x6A, 0x77]));
+    assert_eq!((paged_scope.hits(), paged_scope.misses()), (0, 1));
+
+    // neighbouring fields on the same page hit the cache
+    paged_scope.adjust(1isize);
+    (lens.set)(&mut paged_scope, 0xDEADBEEF);
+    assert_eq!((paged_scope.hits(), paged_scope.misses()), (2, 1));
+
+    // touching more pages than the cache holds evicts and writes back
+    for pos in (0..1024).step_by(64) {
+        paged_scope.adjust(pos as usize);
+        (lens.set)(&mut paged_scope, pos);
+    }
+    // page 1 is still cached from before
+    assert_eq!(paged_scope.misses(), 1 + 15);
+
+    let output = paged_scope.into_inner().unwrap().into_inner();
+    let mut expected = input;
+    expected[404..408].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+    for pos in (0..1024u32).step_by(64) {
+        let start = pos as usize * 4;
+        expected[start..(start + 4)].copy_from_slice(&pos.to_le_bytes());
+    }
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_paged_scope_transform() {
+    use std::io::Cursor;
+    use crate::packed_bit_scope::*;
+
+    // 9 bit fields straddle the 10 byte pages
+    let input: Vec<u8> = (0..1000u32).map(|index| (index * 7) as u8).collect();
+    let mut paged_scope = PagedScope::new(Cursor::new(input.clone()), 10, 3, 9).unwrap();
+    let mut packed_scope = PackedBitScope::with_words(input, 9);
+
+    let increment = |val: u16| (val + 1) & 0x1FF;
+    let transforms = vec!(Transform::make_transform(PagedScope::num_lens::<u16>(), (0..880usize).step_by(3), increment),
+                          Transform::make_transform(PagedScope::num_lens::<u16>(), (5..880usize).step_by(5), increment));
+    apply_many(transforms, &mut paged_scope);
+
+    let transforms = vec!(Transform::make_transform(PackedBitScope::num_lens::<u16>(), (0..880usize).step_by(3), increment),
+                          Transform::make_transform(PackedBitScope::num_lens::<u16>(), (5..880usize).step_by(5), increment));
+    apply_many(transforms, &mut packed_scope);
+
+    let bits = PagedScope::bit_lens();
+    paged_scope.adjust(0usize);
+    let low_bit = (bits.view)(&paged_scope);
+    (bits.set)(&mut paged_scope, !low_bit);
+    packed_scope.bytes[0] ^= 1;
+
+    assert!(paged_scope.hits() > paged_scope.misses());
+    assert_eq!(paged_scope.into_inner().unwrap().into_inner(), packed_scope.bytes);
+}
+
+#[test]
+fn test_paged_scope_write_errors() {
+    use std::io::Cursor;
+
+    // storage whose writes fail while the flag is set
+    struct Flaky {
+        bytes: Cursor<Vec<u8>>,
+        failing: Rc<Cell<bool>>,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.bytes.read(buf)
+        }
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.failing.get() {
+                return Err(io::Error::other("write failed"));
+            }
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.bytes.seek(pos)
+        }
+    }
+
+    let failing = Rc::new(Cell::new(false));
+    let flaky = Flaky { bytes: Cursor::new(vec!(0; 8)), failing: failing.clone() };
+    let mut paged_scope = PagedScope::new(flaky, 4, 1, 4).unwrap();
+    let lens = PagedScope::num_lens::<i8>();
+
+    // 4 bit fields read back sign extended
+    (lens.set)(&mut paged_scope, -3);
+    assert_eq!((lens.view)(&paged_scope), -3);
+
+    // the dirty page can not be evicted, so it is kept and the error reported
+    failing.set(true);
+    paged_scope.adjust(8usize);
+    assert_eq!((lens.view)(&paged_scope), 0);
+    assert!(paged_scope.flush().is_err());
+
+    failing.set(false);
+    paged_scope.flush().unwrap();
+    let flaky = paged_scope.into_inner().unwrap();
+    assert_eq!(flaky.bytes.into_inner(), vec!(0x0D, 0, 0, 0, 0, 0, 0, 0));
+
+    // dropping the scope writes back its dirty pages
+    let mut bytes = vec!(0u8; 8);
+    {
+        let mut paged_scope = PagedScope::new(Cursor::new(&mut bytes), 4, 2, 8).unwrap();
+        paged_scope.adjust(5usize);
+        (PagedScope::num_lens::<u8>().set)(&mut paged_scope, 0xAB);
+    }
+    assert_eq!(bytes, vec!(0, 0, 0, 0, 0, 0xAB, 0, 0));
+}
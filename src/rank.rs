@@ -0,0 +1,297 @@
+use std::rc::Rc;
+
+use crate::bit_vec_scope::*;
+use crate::lens::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+// bits covered by each entry of a RankIndex
+const BLOCK_BYTES: usize = 64;
+const BLOCK_BITS: usize = BLOCK_BYTES * 8;
+
+// A RankIndex stores, for every 512 bit block of a BitVecScope, the number
+// of set bits before the block. Rank and select use it to skip whole
+// blocks, and only count bits within a single block. Writes made through
+// RankIndex::set keep it up to date; after writing the bytes directly,
+// call rebuild. A RankedScope pairs a scope with its index so that writes
+// through its lens, including transforms, always keep the index current.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RankIndex {
+    blocks: Vec<usize>,
+}
+
+impl RankIndex {
+    pub fn new(bit_vec_scope: &BitVecScope) -> RankIndex {
+        let mut index = RankIndex {
+            blocks: Vec::new(),
+        };
+        index.rebuild(bit_vec_scope);
+        index
+    }
+
+    pub fn rebuild(&mut self, bit_vec_scope: &BitVecScope) {
+        self.blocks.clear();
+
+        let mut total = 0;
+        self.blocks.push(0);
+        for block in bit_vec_scope.bytes.chunks(BLOCK_BYTES) {
+            total += block.iter().map(|byte| byte.count_ones() as usize).sum::<usize>();
+            self.blocks.push(total);
+        }
+    }
+
+    // number of set bits in the scope
+    pub fn count_ones(&self) -> usize {
+        self.blocks[self.blocks.len() - 1]
+    }
+
+    // record that the bit at pos changed from old to new
+    pub fn update(&mut self, pos: usize, old: bool, new: bool) {
+        if old == new {
+            return;
+        }
+
+        for count in self.blocks[(pos / BLOCK_BITS) + 1..].iter_mut() {
+            if new {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+
+    // write the bit under the cursor and update the index
+    pub fn set(&mut self, bit_vec_scope: &mut BitVecScope, a: bool) {
+        let old = get_vec_scope(bit_vec_scope);
+        set_vec_scope(bit_vec_scope, a);
+        self.update(bit_vec_scope.pos, old, a);
+    }
+
+    // number of set bits before pos
+    pub fn rank(&self, bit_vec_scope: &BitVecScope, pos: usize) -> usize {
+        let pos = pos.min(bit_vec_scope.shape());
+        let block = pos / BLOCK_BITS;
+        let byte_index = pos / 8;
+
+        let mut count = self.blocks[block];
+        for byte in bit_vec_scope.bytes[(block * BLOCK_BYTES)..byte_index].iter() {
+            count += byte.count_ones() as usize;
+        }
+        if !pos.is_multiple_of(8) {
+            count += (bit_vec_scope.bytes[byte_index] & (0xFF >> (8 - (pos % 8)))).count_ones() as usize;
+        }
+        count
+    }
+
+    // number of clear bits before pos
+    pub fn rank_zeros(&self, bit_vec_scope: &BitVecScope, pos: usize) -> usize {
+        pos.min(bit_vec_scope.shape()) - self.rank(bit_vec_scope, pos)
+    }
+
+    // position of the set bit with k set bits before it
+    pub fn select(&self, bit_vec_scope: &BitVecScope, k: usize) -> Option<usize> {
+        if k >= self.count_ones() {
+            return None;
+        }
+
+        // the last block starting with at most k set bits before it
+        let block = self.blocks.partition_point(|count| *count <= k) - 1;
+        select_from(&bit_vec_scope.bytes, block * BLOCK_BYTES, k - self.blocks[block], true)
+    }
+
+    // position of the clear bit with k clear bits before it
+    pub fn select_zeros(&self, bit_vec_scope: &BitVecScope, k: usize) -> Option<usize> {
+        if k >= bit_vec_scope.shape() - self.count_ones() {
+            return None;
+        }
+
+        // the clear bits before each block grow with the block, so the
+        // last block starting with at most k of them is found by bisection
+        let zeros_before = |block: usize| (block * BLOCK_BITS) - self.blocks[block];
+        let (mut low, mut high) = (0, self.blocks.len() - 1);
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if zeros_before(mid) <= k {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        select_from(&bit_vec_scope.bytes, low * BLOCK_BYTES, k - zeros_before(low), false)
+    }
+
+    // move the cursor to the k-th set bit, leaving it in place if there
+    // are not that many.
+    pub fn seek_select(&self, bit_vec_scope: &mut BitVecScope, k: usize) -> Option<usize> {
+        let found = self.select(bit_vec_scope, k);
+        if let Some(pos) = found {
+            bit_vec_scope.adjust(pos);
+        }
+        found
+    }
+}
+
+// position of the bit equal to value with remaining such bits before it,
+// searching from the byte start
+fn select_from(bytes: &[u8], start: usize, mut remaining: usize, value: bool) -> Option<usize> {
+    for (offset, byte) in bytes[start..].iter().enumerate() {
+        let mut byte = if value { *byte } else { !*byte };
+        let matching = byte.count_ones() as usize;
+        if remaining < matching {
+            for _ in 0..remaining {
+                byte &= byte - 1;
+            }
+            return Some(((start + offset) * 8) + byte.trailing_zeros() as usize);
+        }
+        remaining -= matching;
+    }
+
+    None
+}
+
+
+// A BitVecScope with a RankIndex that its lens keeps up to date. The
+// scope is only lent out immutably so it can not be written around the
+// index.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RankedScope {
+    scope: BitVecScope,
+    index: RankIndex,
+}
+
+impl RankedScope {
+    pub fn new(scope: BitVecScope) -> RankedScope {
+        let index = RankIndex::new(&scope);
+        RankedScope {
+            scope,
+            index,
+        }
+    }
+
+    pub fn scope(&self) -> &BitVecScope {
+        &self.scope
+    }
+
+    pub fn index(&self) -> &RankIndex {
+        &self.index
+    }
+
+    pub fn into_inner(self) -> BitVecScope {
+        self.scope
+    }
+
+    pub fn lens() -> Lens<RankedScope, bool> {
+        lens(Rc::new(|ranked: &RankedScope| get_vec_scope(&ranked.scope)),
+             Rc::new(|ranked: &mut RankedScope, a: bool| ranked.index.set(&mut ranked.scope, a)))
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.index.count_ones()
+    }
+
+    pub fn rank(&self, pos: usize) -> usize {
+        self.index.rank(&self.scope, pos)
+    }
+
+    pub fn rank_zeros(&self, pos: usize) -> usize {
+        self.index.rank_zeros(&self.scope, pos)
+    }
+
+    pub fn select(&self, k: usize) -> Option<usize> {
+        self.index.select(&self.scope, k)
+    }
+
+    pub fn select_zeros(&self, k: usize) -> Option<usize> {
+        self.index.select_zeros(&self.scope, k)
+    }
+
+    pub fn seek_select(&mut self, k: usize) -> Option<usize> {
+        self.index.seek_select(&mut self.scope, k)
+    }
+}
+
+impl Shape for RankedScope {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.scope.shape()
+    }
+}
+
+impl Position for RankedScope {
+    fn position(&self) -> usize {
+        self.scope.position()
+    }
+}
+
+impl<I> Scope<I> for RankedScope
+    where BitVecScope: Scope<I> {
+    fn adjust(&mut self, index: I) {
+        self.scope.adjust(index);
+    }
+}
+
+#[test]
+fn test_rank_select() {
+    let mut bytes = vec!(0u8; 200);
+    bytes[0] = 0x03;
+    bytes[70] = 0x10;
+    bytes[199] = 0x80;
+    let mut bit_vec_scope = BitVecScope::with_bytes(bytes).unwrap();
+    let mut index = RankIndex::new(&bit_vec_scope);
+
+    assert_eq!(index.count_ones(), 4);
+    assert_eq!(index.rank(&bit_vec_scope, 1), 1);
+    assert_eq!(index.rank(&bit_vec_scope, 565), 3);
+    assert_eq!(index.rank(&bit_vec_scope, 1600), 4);
+    assert_eq!(index.rank_zeros(&bit_vec_scope, 10), 8);
+
+    assert_eq!(index.select(&bit_vec_scope, 0), Some(0));
+    assert_eq!(index.select(&bit_vec_scope, 2), Some(564));
+    assert_eq!(index.select(&bit_vec_scope, 3), Some(1599));
+    assert_eq!(index.select(&bit_vec_scope, 4), None);
+
+    // writes through the index keep rank and select current
+    bit_vec_scope.adjust(100usize);
+    index.set(&mut bit_vec_scope, true);
+    assert_eq!(index.rank(&bit_vec_scope, 600), 4);
+    assert_eq!(index.seek_select(&mut bit_vec_scope, 3), Some(564));
+    assert_eq!(bit_vec_scope.position(), 564);
+
+    index.set(&mut bit_vec_scope, false);
+    assert_eq!(index, RankIndex::new(&bit_vec_scope));
+    assert_eq!(index.seek_select(&mut bit_vec_scope, 4), None);
+    assert_eq!(bit_vec_scope.position(), 564);
+
+    // clear bits are selected the same way
+    let bit_vec_scope = BitVecScope::with_bytes(vec!(0xFF, 0x00, 0xFF)).unwrap();
+    let index = RankIndex::new(&bit_vec_scope);
+    assert_eq!(index.select_zeros(&bit_vec_scope, 0), Some(8));
+    assert_eq!(index.select_zeros(&bit_vec_scope, 7), Some(15));
+    assert_eq!(index.select_zeros(&bit_vec_scope, 8), None);
+}
+
+#[test]
+fn test_ranked_scope() {
+    let mut bytes = vec!(0u8; 200);
+    bytes[0] = 0x03;
+    bytes[199] = 0x80;
+    let mut ranked_scope = RankedScope::new(BitVecScope::with_bytes(bytes).unwrap());
+
+    // a transform through the lens keeps the index current
+    let transform = Transform::make_transform(RankedScope::lens(), (600usize..1000).step_by(100), |bit| !bit);
+    transform.transform(&mut ranked_scope);
+    assert_eq!(ranked_scope.count_ones(), 7);
+    assert_eq!(ranked_scope.rank(1000), 6);
+    assert_eq!(ranked_scope.select(5), Some(900));
+    // bits 0 and 1 are set, so 598 clear bits come before 600, which is set
+    assert_eq!(ranked_scope.select_zeros(598), Some(601));
+    assert_eq!(ranked_scope.index(), &RankIndex::new(ranked_scope.scope()));
+
+    assert_eq!(ranked_scope.seek_select(2), Some(600));
+    assert_eq!(ranked_scope.position(), 600);
+    (RankedScope::lens().set)(&mut ranked_scope, false);
+    assert_eq!(ranked_scope.rank_zeros(1000), 1000 - 5);
+}
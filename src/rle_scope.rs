@@ -0,0 +1,254 @@
+use num::clamp;
+use std::iter::FromIterator;
+
+use myopic::lens::lens::*;
+use myopic::lens::*;
+
+use crate::optic::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+/* Run Length Encoded Scope */
+// An RleScope stores its elements as runs of (value, length), so long runs
+// of identical values take constant space. The cursor keeps the run it is
+// in and where that run starts, so moving to a neighbouring position only
+// walks from the current run, making sequential movement amortized O(1).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RleScope<A> {
+    runs: Vec<(A, usize)>,
+    len: usize,
+    pos: usize,
+    run: usize,
+    run_start: usize,
+}
+
+impl<A: Copy + PartialEq> RleScope<A> {
+    pub fn with_vec(vec: Vec<A>) -> Option<RleScope<A>> {
+        RleScope::with_runs(vec.into_iter().map(|a| (a, 1)).collect())
+    }
+
+    pub fn from_elem(a: A, len: usize) -> Option<RleScope<A>> {
+        RleScope::with_runs(vec!((a, len)))
+    }
+
+    // build a scope from runs, dropping empty runs and merging neighbouring
+    // runs with the same value.
+    pub fn with_runs(runs: Vec<(A, usize)>) -> Option<RleScope<A>> {
+        let mut merged: Vec<(A, usize)> = Vec::with_capacity(runs.len());
+        for (a, count) in runs {
+            if count == 0 {
+                continue;
+            }
+
+            match merged.last_mut() {
+                Some(last) if last.0 == a => last.1 += count,
+                _ => merged.push((a, count)),
+            }
+        }
+
+        let len = merged.iter().map(|run| run.1).sum();
+        if len > 0 {
+            Some(RleScope {
+                runs: merged,
+                len,
+                pos: 0,
+                run: 0,
+                run_start: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn runs(&self) -> &[(A, usize)] {
+        &self.runs
+    }
+
+    pub fn to_vec(&self) -> Vec<A> {
+        let mut vec = Vec::with_capacity(self.len);
+        for &(a, count) in self.runs.iter() {
+            vec.extend(std::iter::repeat_n(a, count));
+        }
+        vec
+    }
+
+    pub fn lens() -> impl Optical<Input=RleScope<A>, Output=A> + SliceLens {
+        let lens: Lens<_, _, RleScope<A>, A> =
+            Lens::new(|rle: &RleScope<A>| get_rle_scope(rle),
+                      |rle: &mut RleScope<A>, a: A| set_rle_scope(rle, a));
+
+        lens
+    }
+
+    // move the cursor to pos, walking runs from the current one
+    fn move_to(&mut self, pos: usize) {
+        while pos < self.run_start {
+            self.run -= 1;
+            self.run_start -= self.runs[self.run].1;
+        }
+
+        while pos >= self.run_start + self.runs[self.run].1 {
+            self.run_start += self.runs[self.run].1;
+            self.run += 1;
+        }
+
+        self.pos = pos;
+    }
+
+    // merge the run at index run with the run after it if they match
+    fn merge_next(&mut self, run: usize) {
+        if run + 1 < self.runs.len() && self.runs[run].0 == self.runs[run + 1].0 {
+            self.runs[run].1 += self.runs[run + 1].1;
+            self.runs.remove(run + 1);
+        }
+    }
+}
+
+// panics if the iterator is empty
+impl<A: Copy + PartialEq> FromIterator<A> for RleScope<A> {
+    fn from_iter<It: IntoIterator<Item=A>>(iter: It) -> RleScope<A> {
+        RleScope::with_runs(iter.into_iter().map(|a| (a, 1)).collect()).expect("RleScope needs at least one element")
+    }
+}
+
+impl<A> Shape for RleScope<A> {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.len
+    }
+}
+
+impl<A> Position for RleScope<A> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+pub fn get_rle_scope<A: Copy>(rle_scope: &RleScope<A>) -> A {
+    rle_scope.runs[rle_scope.run].0
+}
+
+pub fn set_rle_scope<A: Copy + PartialEq>(rle_scope: &mut RleScope<A>, a: A) {
+    let run = rle_scope.run;
+    let (value, count) = rle_scope.runs[run];
+    if value == a {
+        return;
+    }
+
+    let offset = rle_scope.pos - rle_scope.run_start;
+
+    if count == 1 {
+        rle_scope.runs[run].0 = a;
+        rle_scope.merge_next(run);
+        if run > 0 && rle_scope.runs[run - 1].0 == a {
+            rle_scope.run_start -= rle_scope.runs[run - 1].1;
+            rle_scope.run -= 1;
+            rle_scope.merge_next(run - 1);
+        }
+    } else if offset == 0 {
+        rle_scope.runs[run].1 -= 1;
+        if run > 0 && rle_scope.runs[run - 1].0 == a {
+            rle_scope.runs[run - 1].1 += 1;
+            rle_scope.run_start -= rle_scope.runs[run - 1].1 - 1;
+            rle_scope.run -= 1;
+        } else {
+            rle_scope.runs.insert(run, (a, 1));
+        }
+    } else if offset == count - 1 {
+        rle_scope.runs[run].1 -= 1;
+        if run + 1 < rle_scope.runs.len() && rle_scope.runs[run + 1].0 == a {
+            rle_scope.runs[run + 1].1 += 1;
+        } else {
+            rle_scope.runs.insert(run + 1, (a, 1));
+        }
+        rle_scope.run_start += count - 1;
+        rle_scope.run += 1;
+    } else {
+        rle_scope.runs[run].1 = offset;
+        rle_scope.runs.insert(run + 1, (a, 1));
+        rle_scope.runs.insert(run + 2, (value, count - offset - 1));
+        rle_scope.run_start += offset;
+        rle_scope.run += 1;
+    }
+}
+
+impl<A: Copy + PartialEq> Scope<usize> for RleScope<A> {
+    fn adjust(&mut self, pos: usize) {
+        let pos = clamp(pos, 0, self.len - 1);
+        self.move_to(pos);
+    }
+}
+
+impl<A: Copy + PartialEq> Scope<isize> for RleScope<A> {
+    fn adjust(&mut self, offset: isize) {
+        let pos = clamp((self.pos as isize) + offset, 0, (self.len - 1) as isize) as usize;
+        self.move_to(pos);
+    }
+}
+
+crate::impl_typed_indices!([A: Copy + PartialEq] RleScope<A>);
+
+#[test]
+fn test_rle_scope() {
+    let mut rle_scope = RleScope::with_vec(vec!(1, 1, 1, 2, 2, 3)).unwrap();
+    let rle_lens = RleScope::lens();
+
+    assert_eq!(rle_scope.runs(), &[(1, 3), (2, 2), (3, 1)]);
+    assert_eq!(rle_scope.shape(), 6);
+
+    // split the middle of a run
+    rle_scope.adjust(1usize);
+    rle_lens.set(&mut rle_scope, 5);
+    assert_eq!(rle_scope.runs(), &[(1, 1), (5, 1), (1, 1), (2, 2), (3, 1)]);
+    assert_eq!(rle_lens.get(&rle_scope), 5);
+
+    // merging back restores the single run
+    rle_lens.set(&mut rle_scope, 1);
+    assert_eq!(rle_scope.runs(), &[(1, 3), (2, 2), (3, 1)]);
+    assert_eq!(rle_lens.get(&rle_scope), 1);
+
+    // extend a neighbouring run from either end
+    rle_scope.adjust(1isize);
+    rle_lens.set(&mut rle_scope, 2);
+    assert_eq!(rle_scope.runs(), &[(1, 2), (2, 3), (3, 1)]);
+
+    rle_scope.adjust(100isize);
+    rle_lens.set(&mut rle_scope, 2);
+    assert_eq!(rle_scope.runs(), &[(1, 2), (2, 4)]);
+    assert_eq!(rle_scope.to_vec(), vec!(1, 1, 2, 2, 2, 2));
+
+    rle_scope.adjust(-5isize);
+    assert_eq!(rle_lens.get(&rle_scope), 1);
+    rle_lens.set(&mut rle_scope, 2);
+    assert_eq!(rle_scope.runs(), &[(2, 1), (1, 1), (2, 4)]);
+
+    rle_scope.adjust(1isize);
+    rle_lens.set(&mut rle_scope, 2);
+    assert_eq!(rle_scope.runs(), &[(2, 6)]);
+}
+
+#[test]
+fn test_rle_scope_transform() {
+    let mut rle_scope = RleScope::from_elem(0u32, 1_000).unwrap();
+
+    let transform =
+        Transform::make_transform(RleScope::lens(), (100..200usize).chain(500..1000), |val| val + 1);
+    transform.transform(&mut rle_scope);
+
+    assert_eq!(rle_scope.runs(), &[(0, 100), (1, 100), (0, 300), (1, 500)]);
+
+    let mut expected = vec!(0u32; 1_000);
+    for index in (100..200).chain(500..1000) {
+        expected[index] += 1;
+    }
+    assert_eq!(rle_scope.to_vec(), expected);
+}
+
+#[test]
+fn test_rle_scope_from_iter() {
+    let rle_scope: RleScope<u8> = vec!(1, 1, 1, 2, 2, 3).into_iter().collect();
+    assert_eq!(rle_scope.runs(), &[(1, 3), (2, 2), (3, 1)]);
+    assert_eq!(rle_scope.to_vec(), vec!(1, 1, 1, 2, 2, 3));
+}
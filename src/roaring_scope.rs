@@ -0,0 +1,516 @@
+use num::clamp;
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+use crate::bit_vec_scope::BitVecScope;
+use crate::lens::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+// containers holding more than this many bits are stored as bitmaps
+const ARRAY_MAX: usize = 4096;
+const BITMAP_WORDS: usize = 1024;
+
+/* Roaring Scope */
+// A RoaringScope is a compressed bitmap. Positions are split into 64K
+// chunks keyed by their high bits, and each chunk with any bits set gets a
+// container for its low 16 bits: a sorted array when sparse, a plain bitmap
+// when dense, or a list of runs when clustered (see optimize).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RoaringScope {
+    containers: Vec<(usize, Container)>,
+    len: usize,
+    pos: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Container {
+    Array(Vec<u16>),
+    Bitmap { words: Vec<u64>, count: usize },
+    // inclusive (start, last) pairs, sorted and non-adjacent
+    Run(Vec<(u16, u16)>),
+}
+
+impl Container {
+    pub fn cardinality(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap { count, .. } => *count,
+            Container::Run(runs) =>
+                runs.iter().map(|&(start, last)| (last - start) as usize + 1).sum(),
+        }
+    }
+
+    pub fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&low).is_ok(),
+            Container::Bitmap { words, .. } =>
+                (words[low as usize / 64] & (1 << (low % 64))) != 0,
+            Container::Run(runs) => run_search(runs, low).is_ok(),
+        }
+    }
+
+    fn insert(&mut self, low: u16) {
+        match self {
+            Container::Array(values) => {
+                if let Err(index) = values.binary_search(&low) {
+                    values.insert(index, low);
+                }
+            },
+
+            Container::Bitmap { words, count } => {
+                let word = &mut words[low as usize / 64];
+                if (*word & (1 << (low % 64))) == 0 {
+                    *word |= 1 << (low % 64);
+                    *count += 1;
+                }
+            },
+
+            Container::Run(runs) => {
+                if let Err(index) = run_search(runs, low) {
+                    let joins_prev = index > 0 && runs[index - 1].1 as u32 + 1 == low as u32;
+                    let joins_next = index < runs.len() && runs[index].0 as u32 == low as u32 + 1;
+                    match (joins_prev, joins_next) {
+                        (true, true) => {
+                            runs[index - 1].1 = runs[index].1;
+                            runs.remove(index);
+                        },
+                        (true, false) => runs[index - 1].1 = low,
+                        (false, true) => runs[index].0 = low,
+                        (false, false) => runs.insert(index, (low, low)),
+                    }
+                }
+            },
+        }
+
+        if self.cardinality() > ARRAY_MAX {
+            if let Container::Array(_) = self {
+                *self = Container::from_words(self.to_words());
+            }
+        }
+    }
+
+    fn remove(&mut self, low: u16) {
+        match self {
+            Container::Array(values) => {
+                if let Ok(index) = values.binary_search(&low) {
+                    values.remove(index);
+                }
+            },
+
+            Container::Bitmap { words, count } => {
+                let word = &mut words[low as usize / 64];
+                if (*word & (1 << (low % 64))) != 0 {
+                    *word &= !(1 << (low % 64));
+                    *count -= 1;
+                }
+            },
+
+            Container::Run(runs) => {
+                if let Ok(index) = run_search(runs, low) {
+                    let (start, last) = runs[index];
+                    if start == last {
+                        runs.remove(index);
+                    } else if low == start {
+                        runs[index].0 = low + 1;
+                    } else if low == last {
+                        runs[index].1 = low - 1;
+                    } else {
+                        runs[index].1 = low - 1;
+                        runs.insert(index + 1, (low + 1, last));
+                    }
+                }
+            },
+        }
+
+        if let Container::Bitmap { .. } = self {
+            if self.cardinality() <= ARRAY_MAX {
+                *self = Container::from_words(self.to_words());
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item=u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().cloned()),
+            Container::Bitmap { words, .. } => Box::new(WordBits::new(words).map(|bit| bit as u16)),
+            Container::Run(runs) =>
+                Box::new(runs.iter().flat_map(|&(start, last)| start..=last)),
+        }
+    }
+
+    fn to_words(&self) -> Vec<u64> {
+        match self {
+            Container::Bitmap { words, .. } => words.clone(),
+            _ => {
+                let mut words = vec!(0; BITMAP_WORDS);
+                for low in self.iter() {
+                    words[low as usize / 64] |= 1 << (low % 64);
+                }
+                words
+            },
+        }
+    }
+
+    // build an array or bitmap container, whichever suits the cardinality
+    fn from_words(words: Vec<u64>) -> Container {
+        let count = words.iter().map(|word| word.count_ones() as usize).sum();
+        if count <= ARRAY_MAX {
+            Container::Array(WordBits::new(&words).map(|bit| bit as u16).collect())
+        } else {
+            Container::Bitmap { words, count }
+        }
+    }
+
+    // pick the smallest of the three representations
+    fn optimize(&self) -> Container {
+        let mut runs: Vec<(u16, u16)> = Vec::new();
+        for low in self.iter() {
+            match runs.last_mut() {
+                Some(run) if run.1 as u32 + 1 == low as u32 => run.1 = low,
+                _ => runs.push((low, low)),
+            }
+        }
+
+        let cardinality = self.cardinality();
+        let run_bytes = runs.len() * 4;
+        let other_bytes = if cardinality <= ARRAY_MAX { cardinality * 2 } else { BITMAP_WORDS * 8 };
+        if run_bytes < other_bytes {
+            Container::Run(runs)
+        } else {
+            Container::from_words(self.to_words())
+        }
+    }
+}
+
+// find the run containing low, or the index where a run starting at low
+// would be inserted.
+fn run_search(runs: &[(u16, u16)], low: u16) -> Result<usize, usize> {
+    match runs.binary_search_by_key(&low, |run| run.0) {
+        Ok(index) => Ok(index),
+        Err(index) if index > 0 && runs[index - 1].1 >= low => Ok(index - 1),
+        Err(index) => Err(index),
+    }
+}
+
+// iterate the set bits of a slice of words, in increasing order
+struct WordBits<'a> {
+    words: &'a [u64],
+    index: usize,
+    word: u64,
+}
+
+impl<'a> WordBits<'a> {
+    fn new(words: &'a [u64]) -> WordBits<'a> {
+        WordBits {
+            words,
+            index: 0,
+            word: if words.is_empty() { 0 } else { words[0] },
+        }
+    }
+}
+
+impl<'a> Iterator for WordBits<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            self.index += 1;
+            if self.index >= self.words.len() {
+                return None;
+            }
+            self.word = self.words[self.index];
+        }
+
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some((self.index * 64) + bit)
+    }
+}
+
+impl RoaringScope {
+    pub fn new(len: usize) -> Option<RoaringScope> {
+        if len > 0 {
+            Some(RoaringScope {
+                containers: Vec::new(),
+                len,
+                pos: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn from_bit_vec_scope(bit_vec_scope: &BitVecScope) -> RoaringScope {
+        let mut roaring_scope = RoaringScope::new(bit_vec_scope.shape()).unwrap();
+        for (byte_index, byte) in bit_vec_scope.bytes.iter().enumerate() {
+            for bit_index in 0..8 {
+                if (byte & (1 << bit_index)) != 0 {
+                    roaring_scope.set((byte_index * 8) + bit_index, true);
+                }
+            }
+        }
+        roaring_scope
+    }
+
+    pub fn lens() -> Lens<RoaringScope, bool> {
+        lens(Rc::new(|roaring: &RoaringScope| get_roaring_scope(roaring)),
+             Rc::new(|roaring: &mut RoaringScope, a: bool| set_roaring_scope(roaring, a)))
+    }
+
+    pub fn containers(&self) -> &[(usize, Container)] {
+        &self.containers
+    }
+
+    pub fn contains(&self, pos: usize) -> bool {
+        match self.container_index(pos >> 16) {
+            Ok(index) => self.containers[index].1.contains(pos as u16),
+            Err(_) => false,
+        }
+    }
+
+    pub fn set(&mut self, pos: usize, a: bool) {
+        assert!(pos < self.len, "RoaringScope position {} out of range for length {}", pos, self.len);
+
+        let key = pos >> 16;
+        match self.container_index(key) {
+            Ok(index) => {
+                if a {
+                    self.containers[index].1.insert(pos as u16);
+                } else {
+                    self.containers[index].1.remove(pos as u16);
+                    if self.containers[index].1.cardinality() == 0 {
+                        self.containers.remove(index);
+                    }
+                }
+            },
+
+            Err(index) => {
+                if a {
+                    self.containers.insert(index, (key, Container::Array(vec!(pos as u16))));
+                }
+            },
+        }
+    }
+
+    // number of set bits
+    pub fn count_ones(&self) -> usize {
+        self.containers.iter().map(|(_, container)| container.cardinality()).sum()
+    }
+
+    // positions of the set bits in increasing order, usable as the indices
+    // of a Transform to mask it to this bitmap.
+    pub fn ones(&self) -> impl Iterator<Item=usize> + '_ {
+        self.containers.iter().flat_map(|(key, container)| {
+            let base = key << 16;
+            container.iter().map(move |low| base + low as usize)
+        })
+    }
+
+    // restrict an index stream to the positions set in this bitmap
+    pub fn mask<'a, It>(&'a self, indices: It) -> impl Iterator<Item=usize> + 'a
+        where It: IntoIterator<Item=usize>,
+              It::IntoIter: 'a {
+        indices.into_iter().filter(move |pos| self.contains(*pos))
+    }
+
+    // convert each container to its smallest representation, using runs
+    // where the bits are clustered.
+    pub fn optimize(&mut self) {
+        for (_, container) in self.containers.iter_mut() {
+            *container = container.optimize();
+        }
+    }
+
+    pub fn union(&self, other: &RoaringScope) -> RoaringScope {
+        self.combine(other, true, |lhs, rhs| lhs | rhs)
+    }
+
+    pub fn intersection(&self, other: &RoaringScope) -> RoaringScope {
+        self.combine(other, false, |lhs, rhs| lhs & rhs)
+    }
+
+    fn container_index(&self, key: usize) -> Result<usize, usize> {
+        self.containers.binary_search_by_key(&key, |container| container.0)
+    }
+
+    // merge the containers of two bitmaps by key. keep_unmatched keeps the
+    // containers found in only one of them, as a union does.
+    fn combine<F>(&self, other: &RoaringScope, keep_unmatched: bool, op: F) -> RoaringScope
+        where F: Fn(u64, u64) -> u64 {
+        let mut containers = Vec::new();
+        let mut lhs = self.containers.iter().peekable();
+        let mut rhs = other.containers.iter().peekable();
+
+        loop {
+            let next = match (lhs.peek(), rhs.peek()) {
+                (Some(l), Some(r)) if l.0 == r.0 => {
+                    let words = l.1.to_words().iter()
+                        .zip(r.1.to_words().iter())
+                        .map(|(lw, rw)| op(*lw, *rw))
+                        .collect();
+                    let key = l.0;
+                    lhs.next();
+                    rhs.next();
+                    Some((key, Container::from_words(words)))
+                },
+                (Some(l), Some(r)) if l.0 < r.0 => lhs.next().filter(|_| keep_unmatched).cloned(),
+                (Some(_), Some(_)) => rhs.next().filter(|_| keep_unmatched).cloned(),
+                (Some(_), None) => lhs.next().filter(|_| keep_unmatched).cloned(),
+                (None, Some(_)) => rhs.next().filter(|_| keep_unmatched).cloned(),
+                (None, None) => break,
+            };
+
+            if let Some((key, container)) = next {
+                if container.cardinality() > 0 {
+                    containers.push((key, container));
+                }
+            }
+        }
+
+        let len = if keep_unmatched { self.len.max(other.len) } else { self.len.min(other.len) };
+        RoaringScope {
+            containers,
+            len,
+            pos: 0,
+        }
+    }
+}
+
+// panics if the iterator is empty
+impl FromIterator<bool> for RoaringScope {
+    fn from_iter<It: IntoIterator<Item=bool>>(iter: It) -> RoaringScope {
+        let mut containers: Vec<(usize, Container)> = Vec::new();
+        let mut len = 0;
+        for bit in iter {
+            if bit {
+                let key = len >> 16;
+                match containers.last_mut() {
+                    Some((last_key, container)) if *last_key == key => container.insert(len as u16),
+                    _ => containers.push((key, Container::Array(vec!(len as u16)))),
+                }
+            }
+            len += 1;
+        }
+
+        let mut roaring_scope = RoaringScope::new(len).expect("RoaringScope needs at least one bit");
+        roaring_scope.containers = containers;
+        roaring_scope
+    }
+}
+
+impl Shape for RoaringScope {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.len
+    }
+}
+
+impl Position for RoaringScope {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+pub fn get_roaring_scope(roaring_scope: &RoaringScope) -> bool {
+    roaring_scope.contains(roaring_scope.pos)
+}
+
+pub fn set_roaring_scope(roaring_scope: &mut RoaringScope, a: bool) {
+    let pos = roaring_scope.pos;
+    roaring_scope.set(pos, a);
+}
+
+impl Scope<usize> for RoaringScope {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.len - 1);
+    }
+}
+
+impl Scope<isize> for RoaringScope {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.len - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!([] RoaringScope);
+
+#[test]
+fn test_roaring_scope() {
+    let mut roaring_scope = RoaringScope::new(1 << 20).unwrap();
+    let lens = RoaringScope::lens();
+
+    assert!(!(lens.view)(&roaring_scope));
+    (lens.set)(&mut roaring_scope, true);
+    roaring_scope.adjust(70_000usize);
+    (lens.set)(&mut roaring_scope, true);
+    roaring_scope.adjust(-1isize);
+    assert!(!(lens.view)(&roaring_scope));
+    assert_eq!(roaring_scope.ones().collect::<Vec<_>>(), vec!(0, 70_000));
+
+    // a dense chunk switches to a bitmap and back to an array
+    for pos in 0..5000 {
+        roaring_scope.set(pos * 2, true);
+    }
+    match roaring_scope.containers()[0].1 {
+        Container::Bitmap { count, .. } => assert_eq!(count, 5000),
+        _ => panic!("expected a bitmap container"),
+    }
+    for pos in 0..1000 {
+        roaring_scope.set(pos * 2, false);
+    }
+    match roaring_scope.containers()[0].1 {
+        Container::Array(ref values) => assert_eq!(values.len(), 4000),
+        _ => panic!("expected an array container"),
+    }
+
+    // clustered bits compress to runs, and stay writable
+    let mut clustered = RoaringScope::new(1 << 17).unwrap();
+    for pos in (100..20_000).chain(70_000..80_000) {
+        clustered.set(pos, true);
+    }
+    clustered.optimize();
+    assert_eq!(clustered.containers()[0].1, Container::Run(vec!((100, 19_999))));
+    clustered.set(500, false);
+    clustered.set(20_000, true);
+    assert_eq!(clustered.containers()[0].1, Container::Run(vec!((100, 499), (501, 20_000))));
+    assert_eq!(clustered.count_ones(), 19_900 + 10_000);
+    assert!(clustered.ones().eq((100..500).chain(501..20_001).chain(70_000..80_000)));
+}
+
+#[test]
+fn test_roaring_scope_set_ops() {
+    let mut evens = RoaringScope::new(200_000).unwrap();
+    let mut threes = RoaringScope::new(200_000).unwrap();
+    for pos in (0..200_000).step_by(2) {
+        evens.set(pos, true);
+    }
+    for pos in (0..200_000).step_by(3) {
+        threes.set(pos, true);
+    }
+
+    let both = evens.intersection(&threes);
+    assert!(both.ones().eq((0..200_000).step_by(6)));
+
+    let either = evens.union(&threes);
+    assert_eq!(either.count_ones(), 100_000 + 66_667 - 33_334);
+    assert!(either.ones().eq((0..200_000).filter(|pos| pos % 2 == 0 || pos % 3 == 0)));
+
+    // use the bitmap as a transform mask
+    let mut vec_scope = crate::vec_scope::VecScope::with_vec(vec!(0u8; 12)).unwrap();
+    let transform = Transform::make_transform(crate::vec_scope::VecScope::lens(),
+                                              both.mask(0..12).collect::<Vec<_>>(),
+                                              |val| val + 1);
+    transform.transform(&mut vec_scope);
+    assert_eq!(vec_scope.vec, vec!(1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0));
+
+    let bit_vec_scope = BitVecScope::with_bytes(vec!(0x81, 0x00, 0x10)).unwrap();
+    let from_bits = RoaringScope::from_bit_vec_scope(&bit_vec_scope);
+    assert_eq!(from_bits.ones().collect::<Vec<_>>(), vec!(0, 7, 20));
+    assert_eq!(from_bits.shape(), 24);
+
+    let collected: RoaringScope = (0..24).map(|pos| from_bits.contains(pos)).collect();
+    assert!(collected == from_bits);
+}
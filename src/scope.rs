@@ -3,12 +3,13 @@ use std::iter::*;
 use std::collections::VecDeque;
 use std::cmp::Ordering;
 
-use num::{PrimInt, zero, one};
+use num::{PrimInt, NumCast, zero, one};
 
 use myopic::*;
 use myopic::lens::lens::*;
 
 use crate::shape::*;
+use crate::optic::*;
 
 
 // NOTE this is just a Monoid Action
@@ -16,6 +17,120 @@ pub trait Scope<I> {
     fn adjust(&mut self, index: I);
 }
 
+// The absolute position of a scope's cursor, in the same units as its Shape.
+pub trait Position {
+    fn position(&self) -> usize;
+}
+
+// Typed indices. Scope<usize> moves the cursor to a position and
+// Scope<isize> moves it by an offset, so adjust(1usize) and adjust(1isize)
+// do quite different things, and an unsuffixed literal depends on
+// inference. Abs and Rel say which is meant, and new code should use them.
+// The usize and isize impls stay as the compatibility path: scope_map and
+// Transform index through them, and trait impls can not be deprecated.
+// Every scope implements the typed indices in terms of them, with
+// impl_typed_indices.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Abs(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Rel(pub isize);
+
+// A move relative to the start, the end or the cursor, as with
+// std::io::SeekFrom. End(-1) is the last position. Moves past either end
+// are clamped. The end of an unbounded scope, like a StreamScope before it
+// has been read through, is not known, so seeking from it panics.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SeekFrom {
+    Start(usize),
+    End(isize),
+    Current(isize),
+}
+
+pub fn seek_from<D>(d: &mut D, from: SeekFrom)
+    where D: Scope<usize> + Scope<isize> + Shape<Shape=usize> {
+    match from {
+        SeekFrom::Start(pos) => d.adjust(pos),
+        SeekFrom::End(offset) => {
+            let len = d.shape();
+            assert!(len != usize::MAX, "can not seek from the end of an unbounded scope");
+            let pos = if offset < 0 {
+                len.saturating_sub(offset.unsigned_abs())
+            } else {
+                len.saturating_add(offset as usize)
+            };
+            d.adjust(pos)
+        },
+        SeekFrom::Current(offset) => d.adjust(offset),
+    }
+}
+
+// Implement Scope<Abs>, Scope<Rel> and Scope<SeekFrom> for a scope with
+// Scope<usize>, Scope<isize> and a usize Shape. The generics of the impl
+// go in brackets:
+//   impl_typed_indices!([A: Copy] VecScope<A>);
+#[macro_export]
+macro_rules! impl_typed_indices {
+    ([$($generics:tt)*] $scope:ty) => {
+        impl<$($generics)*> $crate::scope::Scope<$crate::scope::Abs> for $scope {
+            fn adjust(&mut self, index: $crate::scope::Abs) {
+                $crate::scope::Scope::<usize>::adjust(self, index.0)
+            }
+        }
+
+        impl<$($generics)*> $crate::scope::Scope<$crate::scope::Rel> for $scope {
+            fn adjust(&mut self, index: $crate::scope::Rel) {
+                $crate::scope::Scope::<isize>::adjust(self, index.0)
+            }
+        }
+
+        impl<$($generics)*> $crate::scope::Scope<$crate::scope::SeekFrom> for $scope {
+            fn adjust(&mut self, from: $crate::scope::SeekFrom) {
+                $crate::scope::seek_from(self, from)
+            }
+        }
+    };
+}
+
+// A scope whose cursor can be moved with the typed indices, and queried.
+pub trait Seekable: Scope<Abs> + Scope<Rel> + Scope<SeekFrom> + Position {
+    // move the cursor, returning where it ended up
+    fn seek(&mut self, from: SeekFrom) -> Abs {
+        Scope::<SeekFrom>::adjust(self, from);
+        self.tell()
+    }
+
+    fn tell(&self) -> Abs {
+        Abs(self.position())
+    }
+}
+
+impl<D: Scope<Abs> + Scope<Rel> + Scope<SeekFrom> + Position> Seekable for D {
+}
+
+// An action function is applied to the focus of a lens at a given index.
+// Plain closures ignore the index, while closures wrapped in Indexed
+// receive the index along with the value.
+pub trait ActionFn<I, A> {
+    fn call(&self, index: I, a: A) -> A;
+}
+
+impl<I, A, F> ActionFn<I, A> for F
+    where F: Fn(A) -> A {
+    fn call(&self, _index: I, a: A) -> A {
+        (self)(a)
+    }
+}
+
+pub struct Indexed<F>(pub F);
+
+impl<I, A, F> ActionFn<I, A> for Indexed<F>
+    where F: Fn(I, A) -> A {
+    fn call(&self, index: I, a: A) -> A {
+        (self.0)(index, a)
+    }
+}
+
 pub struct Action<F, O, D, A> {
     pub act: F,
     pub lens: O,
@@ -23,13 +138,60 @@ pub struct Action<F, O, D, A> {
     d: PhantomData<D>,
 }
 
+impl<F, O, D, A> Action<F, O, D, A> {
+    pub fn new(lens: O, f: F) -> Action<F, O, D, A>
+        where F: Fn(A) -> A {
+        Action {
+            act: f,
+            lens,
+            d: PhantomData,
+            a: PhantomData,
+        }
+    }
+
+    pub fn from_action_fn<I>(lens: O, f: F) -> Action<F, O, D, A>
+        where F: ActionFn<I, A> {
+        Action {
+            act: f,
+            lens,
+            d: PhantomData,
+            a: PhantomData,
+        }
+    }
+
+    pub fn new_fallible<E>(lens: O, f: F) -> Action<F, O, D, A>
+        where F: Fn(A) -> Result<A, E> {
+        Action {
+            act: f,
+            lens,
+            d: PhantomData,
+            a: PhantomData,
+        }
+    }
+}
+
+impl<G, O, D, A> Action<Indexed<G>, O, D, A> {
+    pub fn new_indexed<I>(lens: O, f: G) -> Action<Indexed<G>, O, D, A>
+        where G: Fn(I, A) -> A {
+        Action {
+            act: Indexed(f),
+            lens,
+            d: PhantomData,
+            a: PhantomData,
+        }
+    }
+}
+
 impl<F, O, D, A> Action<F, O, D, A> 
-    where O: Getter + Setter + Lensable<Input=D, Output=A>,
-          F: Fn(A) -> A {
-    pub fn act(&self, d: &mut D) {
-        let val = self.lens.get(d);
-        let val_f = (self.act)(val);
-        self.lens.set(d, val_f);
+    where O: Over + Lensable<Input=D, Output=A> {
+    pub fn act(&self, d: &mut D)
+        where F: Fn(A) -> A {
+        self.lens.over(d, &self.act);
+    }
+
+    pub fn act_at<I: Copy>(&self, index: I, d: &mut D)
+        where F: ActionFn<I, A> {
+        self.lens.over(d, |a| self.act.call(index, a));
     }
 }
 
@@ -39,28 +201,41 @@ pub struct Transform<F, O, D, A, Ix> {
 }
 
 impl<F, O, D, I, A, Ix> Transform<F, O, D, A, Ix> 
-    where O: Getter + Setter + Lensable<Input=D, Output=A>,
+    where O: Over + Lensable<Input=D, Output=A>,
           D: Shape + Scope<I>,
-          F: Fn(A) -> A,
+          F: ActionFn<I, A>,
+          I: Copy,
           Ix: Clone + IntoIterator<Item=I> {
     pub fn transform(&self, d: &mut D) {
+        if self.action.lens.over_each(d, self.indices.clone(), |index, a| self.action.act.call(index, a)) {
+            return;
+        }
+
         for index in self.indices.clone().into_iter() {
             d.adjust(index);
 
-            self.action.act(d);
+            self.action.act_at(index, d);
         }
     }
 
     pub fn make_transform(lens: O, indices: Ix, f: F) -> Transform<F, O, D, A, Ix>
         where F: Fn(A) -> A + 'static {
         Transform {
-            action: Action {
-                act: f,
-                lens: lens,
-                d: PhantomData,
-                a: PhantomData,
-            },
+            action: Action::new(lens, f),
+            indices,
+        }
+    }
+}
 
+impl<G, O, D, I, A, Ix> Transform<Indexed<G>, O, D, A, Ix> 
+    where O: Over + Lensable<Input=D, Output=A>,
+          D: Shape + Scope<I>,
+          G: Fn(I, A) -> A,
+          I: Copy,
+          Ix: Clone + IntoIterator<Item=I> {
+    pub fn make_indexed_transform(lens: O, indices: Ix, f: G) -> Transform<Indexed<G>, O, D, A, Ix> {
+        Transform {
+            action: Action::new_indexed(lens, f),
             indices: indices,
         }
     }
@@ -72,45 +247,33 @@ pub fn apply_both<F, G, O, D, A, Ix, I>(first: &Transform<F, O, D, A, Ix>,
     where D: Scope<I> + Shape,
           Ix: IntoIterator<Item=I> + Clone,
           I: PartialOrd + Copy,
-          F: Fn(&mut D),
-          G: Fn(&mut D),
-          O: Getter + Setter + Lensable<Input=D, Output=A> {
-
-    let mut ixs1 = first.indices.clone().into_iter();
-    let mut ixs2 = second.indices.clone().into_iter();
+          F: ActionFn<I, A>,
+          G: ActionFn<I, A>,
+          O: Over + Lensable<Input=D, Output=A> {
 
-    if let (Some(mut ix1), Some(mut ix2)) = (ixs1.next(), ixs2.next()) {
-        loop {
-            if ix1 < ix2 {
-                d.adjust(ix1);
-                (first.action.act)(d);
-
-                if let Some(new_ix) = ixs1.next() {
-                    ix1 = new_ix;
-                } else {
-                    break;
-                }
-            } else {
-                d.adjust(ix2);
-                (second.action.act)(d);
+    let mut ixs1 = first.indices.clone().into_iter().peekable();
+    let mut ixs2 = second.indices.clone().into_iter().peekable();
 
-                if let Some(new_ix) = ixs2.next() {
-                    ix2 = new_ix;
-                } else {
-                    break;
-                }
-            }
+    while let (Some(&ix1), Some(&ix2)) = (ixs1.peek(), ixs2.peek()) {
+        if ix1 < ix2 {
+            d.adjust(ix1);
+            first.action.act_at(ix1, d);
+            ixs1.next();
+        } else {
+            d.adjust(ix2);
+            second.action.act_at(ix2, d);
+            ixs2.next();
         }
     }
 
     for index in ixs1 {
         d.adjust(index);
-        (first.action.act)(d);
+        first.action.act_at(index, d);
     }
 
     for index in ixs2 {
         d.adjust(index);
-        (second.action.act)(d);
+        second.action.act_at(index, d);
     }
 }
 
@@ -132,48 +295,191 @@ impl<I: Ord> Ord for Queued<I> {
     }
 }
 
+// MergedIndices walks the index streams of several transforms in
+// sorted order, yielding each index along with the transform it came from.
+pub struct MergedIndices<It: Iterator> {
+    pqueue: VecDeque<Queued<It::Item>>,
+    ix_vec: Vec<It>,
+}
+
+impl<It, I> MergedIndices<It>
+    where It: Iterator<Item=I>,
+          I: Ord + Copy {
+    pub fn new(mut ix_vec: Vec<It>) -> MergedIndices<It> {
+        let mut pqueue = VecDeque::new();
+
+        for (index, ixs) in ix_vec.iter_mut().enumerate() {
+            if let Some(ix) = ixs.next() {
+                let new_queued = Queued { ix, index };
+
+                if let Some(insert_index) = pqueue.iter().position(|queued: &Queued<I>| queued.ix > ix) {
+                    pqueue.insert(insert_index, new_queued);
+                } else {
+                    pqueue.push_back(new_queued);
+                }
+            }
+        }
+
+        MergedIndices {
+            pqueue,
+            ix_vec,
+        }
+    }
+}
+
+impl<It, I> Iterator for MergedIndices<It>
+    where It: Iterator<Item=I>,
+          I: Ord + Copy {
+    type Item = (I, usize);
+
+    fn next(&mut self) -> Option<(I, usize)> {
+        let queued = self.pqueue.pop_front()?;
+
+        if let Some(ix) = self.ix_vec[queued.index].next() {
+            let new_queued = Queued { ix: ix, index: queued.index };
+
+            // ties are broken by transform order, so transforms at the same
+            // index are applied in the order they were given
+            let index = queued.index;
+            let after = |other: &Queued<I>| other.ix > ix || (other.ix == ix && other.index > index);
+            if let Some(insert_index) = self.pqueue.iter().position(after) {
+                self.pqueue.insert(insert_index, new_queued);
+            } else {
+                self.pqueue.push_back(new_queued);
+            }
+        }
+
+        Some((queued.ix, queued.index))
+    }
+}
+
 pub fn apply_many<F, O, D, A, Ix, I>(transforms: Vec<Transform<F, O, D, A, Ix>>, d: &mut D) 
     where D: Scope<I> + Shape,
           Ix: IntoIterator<Item=I> + Clone,
           I: PartialOrd + Ord + Copy,
-          F: Fn(A) -> A,
-          O: Getter + Setter + Lensable<Input=D, Output=A> {
+          F: ActionFn<I, A>,
+          O: Over + Lensable<Input=D, Output=A> {
+
+    let ix_vec = transforms.iter().map(|transform| transform.indices.clone().into_iter()).collect();
+
+    for (ix, index) in MergedIndices::new(ix_vec) {
+        d.adjust(ix);
+        transforms[index].action.act_at(ix, d);
+    }
+}
 
-    let mut pqueue = VecDeque::new();
-    let mut ix_vec = vec!();
+// Iterators reading a scope through a lens. Reading moves the cursor, so
+// they borrow the scope mutably, and leave the cursor at the last position
+// read. The shape is read again at each step, so a scope which only learns
+// its length as it goes, like a StreamScope, still ends.
+pub trait ScopeIter: Sized {
+    // every value, in position order
+    fn iter_with<'a, L>(&'a mut self, lens: &'a L) -> IterWith<'a, Self, L>
+        where L: Getter + Lensable<Input=Self>;
+
+    // (index, value) pairs for each index of a stream
+    fn iter_at<'a, L, I, Ix>(&'a mut self, lens: &'a L, indices: Ix) -> IterAt<'a, Self, L, Ix::IntoIter>
+        where L: Getter + Lensable<Input=Self>,
+              Self: Scope<I>,
+              I: Copy,
+              Ix: IntoIterator<Item=I>;
+}
 
-    for index in 0..transforms.len() {
-        ix_vec.push(transforms[index].indices.clone().into_iter());
-        if let Some(ix) = ix_vec[index].next() {
-            pqueue.push_back(Queued { ix: ix, index: index });
+impl<D> ScopeIter for D
+    where D: Scope<usize> + Shape<Shape=usize> {
+    fn iter_with<'a, L>(&'a mut self, lens: &'a L) -> IterWith<'a, D, L>
+        where L: Getter + Lensable<Input=D> {
+        IterWith {
+            scope: self,
+            lens,
+            index: 0,
         }
     }
 
-    while let Some(queued) = pqueue.pop_front() {
-        d.adjust(queued.ix);
-        transforms[queued.index].action.act(d);
-        if let Some(ix) = ix_vec[queued.index].next() {
-            let new_queued = Queued { ix: ix, index: queued.index };
+    fn iter_at<'a, L, I, Ix>(&'a mut self, lens: &'a L, indices: Ix) -> IterAt<'a, D, L, Ix::IntoIter>
+        where L: Getter + Lensable<Input=D>,
+              D: Scope<I>,
+              I: Copy,
+              Ix: IntoIterator<Item=I> {
+        IterAt {
+            scope: self,
+            lens,
+            indices: indices.into_iter(),
+        }
+    }
+}
 
-            if let Some(insert_index) = pqueue.iter().position(|queued| queued.ix > ix) {
-                pqueue.insert(insert_index, new_queued);
-            } else {
-                pqueue.push_back(new_queued);
-            }
+pub struct IterWith<'a, D, L> {
+    scope: &'a mut D,
+    lens: &'a L,
+    index: usize,
+}
+
+impl<'a, D, L> Iterator for IterWith<'a, D, L>
+    where D: Scope<usize> + Shape<Shape=usize>,
+          L: Getter + Lensable<Input=D> {
+    type Item = L::Output;
+
+    fn next(&mut self) -> Option<L::Output> {
+        if self.index >= self.scope.shape() {
+            return None;
+        }
+
+        // moving the cursor may reach the end of an unbounded scope
+        self.scope.adjust(self.index);
+        if self.index >= self.scope.shape() {
+            return None;
         }
+
+        self.index += 1;
+        Some(self.lens.get(self.scope))
+    }
+
+    // an unbounded scope may end before its shape says
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.scope.shape().saturating_sub(self.index)))
+    }
+}
+
+pub struct IterAt<'a, D, L, It> {
+    scope: &'a mut D,
+    lens: &'a L,
+    indices: It,
+}
+
+impl<'a, D, L, I, It> Iterator for IterAt<'a, D, L, It>
+    where D: Scope<I>,
+          L: Getter + Lensable<Input=D>,
+          I: Copy,
+          It: Iterator<Item=I> {
+    type Item = (I, L::Output);
+
+    fn next(&mut self) -> Option<(I, L::Output)> {
+        let index = self.indices.next()?;
+        self.scope.adjust(index);
+        Some((index, self.lens.get(self.scope)))
     }
 }
 
 pub fn scope_map<I, O, D, A, F>(d: &mut D, action: Action<F, O, D, A>)
     where D: Scope<I> + Shape<Shape=I>,
           I: PrimInt,
-          F: Fn(A) -> A,
-          O: Getter + Setter + Lensable<Input=D, Output=A> {
-    let cap = d.shape();
+          F: ActionFn<I, A>,
+          O: Over + Lensable<Input=D, Output=A> {
+    // lenses onto a slice loop over it directly
+    if action.lens.over_all(d, |index, a| action.act.call(<I as NumCast>::from(index).unwrap(), a)) {
+        return;
+    }
+
+    // the shape is read again after each move, as an unbounded scope only
+    // finds its end by moving to it
     let mut index = zero();
-    while index != cap {
+    while index < d.shape() {
         d.adjust(index);
-        action.act(d);
+        if index >= d.shape() {
+            break;
+        }
+        action.act_at(index, d);
         index = index + one();
     }
 }
@@ -189,3 +495,38 @@ pub fn scope_ixmap<F, O, I, Ix, D, A>(d: &mut D, ix: Ix, action: Action<F, O, D,
     }
 }
 
+#[test]
+fn test_apply_many_ties() {
+    use crate::vec_scope::*;
+
+    let double: fn(u32) -> u32 = |val| val * 2;
+    let increment: fn(u32) -> u32 = |val| val + 1;
+
+    // transforms at the same index are applied in the order they are given
+    let mut vec_scope = VecScope::with_vec(vec!(1u32, 2, 3)).unwrap();
+    apply_many(vec!(Transform::make_transform(VecScope::lens(), vec!(0usize, 1, 2), double),
+                    Transform::make_transform(VecScope::lens(), vec!(1usize, 2), increment)),
+               &mut vec_scope);
+    assert_eq!(vec_scope.vec, vec!(2, 5, 7));
+
+    let mut vec_scope = VecScope::with_vec(vec!(1u32, 2, 3)).unwrap();
+    apply_many(vec!(Transform::make_transform(VecScope::lens(), vec!(1usize, 2), increment),
+                    Transform::make_transform(VecScope::lens(), vec!(0usize, 1, 2), double)),
+               &mut vec_scope);
+    assert_eq!(vec_scope.vec, vec!(2, 6, 8));
+}
+
+#[test]
+fn test_collect_empty() {
+    use std::panic::catch_unwind;
+    use crate::vec_scope::*;
+    use crate::bit_vec_scope::*;
+    use crate::bit_word_scope::*;
+    use crate::packed_bit_scope::*;
+
+    // every scope holds at least one element, so none can be built empty
+    assert!(catch_unwind(|| Vec::<u8>::new().into_iter().collect::<VecScope<u8>>()).is_err());
+    assert!(catch_unwind(|| Vec::<bool>::new().into_iter().collect::<BitVecScope>()).is_err());
+    assert!(catch_unwind(|| Vec::<u8>::new().into_iter().collect::<BitWordScope<u8>>()).is_err());
+    assert!(catch_unwind(|| Vec::<bool>::new().into_iter().collect::<PackedBitScope>()).is_err());
+}
@@ -0,0 +1,213 @@
+use std::mem;
+
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+
+use crate::vec_scope::*;
+use crate::bit_vec_scope::*;
+use crate::bit_word_scope::*;
+use crate::packed_bit_scope::*;
+
+
+// Scopes serialize their buffer, cursor and packing parameters directly.
+// Deserialization goes through a plain representation of the same fields
+// so the invariants that the constructors and adjust rely on can be
+// checked before a scope is built.
+
+#[derive(Deserialize)]
+struct VecScopeRepr<A> {
+    vec: Vec<A>,
+    pos: usize,
+}
+
+impl<'de, A: Deserialize<'de>> Deserialize<'de> for VecScope<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<VecScope<A>, D::Error> {
+        let repr = VecScopeRepr::deserialize(deserializer)?;
+
+        if repr.vec.is_empty() {
+            return Err(D::Error::custom("VecScope vec must not be empty"));
+        }
+
+        if repr.pos >= repr.vec.len() {
+            return Err(D::Error::custom(format!("VecScope pos {} out of range for length {}",
+                                                repr.pos, repr.vec.len())));
+        }
+
+        Ok(VecScope {
+            vec: repr.vec,
+            pos: repr.pos,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct BitVecScopeRepr {
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl<'de> Deserialize<'de> for BitVecScope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BitVecScope, D::Error> {
+        let repr = BitVecScopeRepr::deserialize(deserializer)?;
+
+        if repr.bytes.is_empty() {
+            return Err(D::Error::custom("BitVecScope bytes must not be empty"));
+        }
+
+        if repr.pos >= repr.bytes.len() * 8 {
+            return Err(D::Error::custom(format!("BitVecScope pos {} out of range for {} bits",
+                                                repr.pos, repr.bytes.len() * 8)));
+        }
+
+        Ok(BitVecScope {
+            bytes: repr.bytes,
+            pos: repr.pos,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PackedBitScopeRepr {
+    bytes: Vec<u8>,
+    pos: usize,
+    bits_used: usize,
+}
+
+impl<'de> Deserialize<'de> for PackedBitScope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PackedBitScope, D::Error> {
+        let repr = PackedBitScopeRepr::deserialize(deserializer)?;
+
+        if repr.bits_used == 0 {
+            return Err(D::Error::custom("PackedBitScope bits_used must be nonzero"));
+        }
+
+        if repr.bits_used > 64 {
+            return Err(D::Error::custom(format!("PackedBitScope bits_used {} is wider than 64", repr.bits_used)));
+        }
+
+        if (repr.bytes.len() * 8) < repr.bits_used {
+            return Err(D::Error::custom("PackedBitScope bytes hold no whole field"));
+        }
+
+        // adjust clamps the cursor to the number of whole fields, inclusive
+        let max_pos = (repr.bytes.len() * 8) / repr.bits_used;
+        if repr.pos > max_pos {
+            return Err(D::Error::custom(format!("PackedBitScope pos {} out of range for {} fields",
+                                                repr.pos, max_pos)));
+        }
+
+        Ok(PackedBitScope {
+            bytes: repr.bytes,
+            pos: repr.pos,
+            bits_used: repr.bits_used,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct BitWordScopeRepr<B> {
+    vec: Vec<B>,
+    bits_used: usize,
+    pos: usize,
+}
+
+impl<'de, B: Deserialize<'de>> Deserialize<'de> for BitWordScope<B> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BitWordScope<B>, D::Error> {
+        let repr = BitWordScopeRepr::deserialize(deserializer)?;
+
+        if repr.bits_used == 0 {
+            return Err(D::Error::custom("BitWordScope bits_used must be nonzero"));
+        }
+
+        let word_bits = mem::size_of::<B>() * 8;
+        if repr.bits_used > word_bits {
+            return Err(D::Error::custom(format!("BitWordScope bits_used {} is wider than its {} bit words",
+                                                repr.bits_used, word_bits)));
+        }
+
+        if repr.vec.is_empty() {
+            return Err(D::Error::custom("BitWordScope vec must not be empty"));
+        }
+
+        let num_bits = repr.vec.len() * repr.bits_used;
+        if repr.pos >= num_bits {
+            return Err(D::Error::custom(format!("BitWordScope pos {} out of range for {} bits",
+                                                repr.pos, num_bits)));
+        }
+
+        Ok(BitWordScope {
+            vec: repr.vec,
+            bits_used: repr.bits_used,
+            pos: repr.pos,
+        })
+    }
+}
+
+
+#[test]
+fn test_serialize_round_trip() {
+    use crate::scope::*;
+
+    let mut vec_scope = VecScope::with_vec(vec!(1u16, 2, 3)).unwrap();
+    vec_scope.adjust(2usize);
+    let json = serde_json::to_string(&vec_scope).unwrap();
+    assert_eq!(json, r#"{"vec":[1,2,3],"pos":2}"#);
+    assert!(serde_json::from_str::<VecScope<u16>>(&json).unwrap() == vec_scope);
+
+    let mut packed_scope = PackedBitScope::with_words(vec!(0xA5, 0x5A), 4);
+    packed_scope.adjust(3usize);
+    let json = serde_json::to_string(&packed_scope).unwrap();
+    assert!(serde_json::from_str::<PackedBitScope>(&json).unwrap() == packed_scope);
+
+    let mut bit_vec_scope = BitVecScope::with_bytes(vec!(0x80)).unwrap();
+    bit_vec_scope.adjust(7usize);
+    let json = serde_json::to_string(&bit_vec_scope).unwrap();
+    assert!(serde_json::from_str::<BitVecScope>(&json).unwrap() == bit_vec_scope);
+
+    let bit_word_scope = BitWordScope::with_words(vec!(1u8, 2), 3);
+    let json = serde_json::to_string(&bit_word_scope).unwrap();
+    assert!(serde_json::from_str::<BitWordScope<u8>>(&json).unwrap() == bit_word_scope);
+}
+
+#[test]
+fn test_serialize_invariants() {
+    assert!(serde_json::from_str::<VecScope<u8>>(r#"{"vec":[],"pos":0}"#).is_err());
+    assert!(serde_json::from_str::<VecScope<u8>>(r#"{"vec":[1],"pos":1}"#).is_err());
+    assert!(serde_json::from_str::<BitVecScope>(r#"{"bytes":[1],"pos":8}"#).is_err());
+    assert!(serde_json::from_str::<PackedBitScope>(r#"{"bytes":[1],"pos":0,"bits_used":0}"#).is_err());
+    assert!(serde_json::from_str::<PackedBitScope>(r#"{"bytes":[1],"pos":3,"bits_used":4}"#).is_err());
+    assert!(serde_json::from_str::<PackedBitScope>(r#"{"bytes":[1,2,3,4,5,6,7,8,9],"pos":0,"bits_used":65}"#).is_err());
+    assert!(serde_json::from_str::<PackedBitScope>(r#"{"bytes":[1],"pos":0,"bits_used":9}"#).is_err());
+    assert!(serde_json::from_str::<PackedBitScope>(r#"{"bytes":[],"pos":0,"bits_used":1}"#).is_err());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[1],"bits_used":0,"pos":0}"#).is_err());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[],"bits_used":3,"pos":0}"#).is_err());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[1],"bits_used":3,"pos":3}"#).is_err());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[1],"bits_used":3,"pos":2}"#).is_ok());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[1],"bits_used":64,"pos":20}"#).is_err());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[1],"bits_used":9,"pos":0}"#).is_err());
+    assert!(serde_json::from_str::<BitWordScope<u8>>(r#"{"vec":[1],"bits_used":8,"pos":7}"#).is_ok());
+
+    let err = serde_json::from_str::<PackedBitScope>(r#"{"bytes":[1],"pos":0,"bits_used":0}"#).err().unwrap();
+    assert!(err.to_string().contains("bits_used must be nonzero"));
+}
+
+#[test]
+fn test_serialize_diff() {
+    use crate::diff::*;
+
+    let old = VecScope::with_vec(vec!(1u8, 2, 3)).unwrap();
+    let new = VecScope::with_vec(vec!(1u8, 5, 3, 4)).unwrap();
+    let diff = old.diff(&new);
+    let json = serde_json::to_string(&diff).unwrap();
+    assert_eq!(json, r#"{"len":3,"edits":[{"start":1,"old":[2],"new":[5]},{"start":3,"old":[],"new":[4]}]}"#);
+
+    let mut scope = old.clone();
+    scope.patch(&serde_json::from_str::<Diff<u8>>(&json).unwrap()).unwrap();
+    assert!(scope == new);
+
+    let old = BitVecScope::with_bytes(vec!(0x01)).unwrap();
+    let new = BitVecScope::with_bytes(vec!(0x06)).unwrap();
+    let diff = old.diff(&new).unwrap();
+    let json = serde_json::to_string(&diff).unwrap();
+    assert_eq!(serde_json::from_str::<BitDiff>(&json).unwrap(), diff);
+}
@@ -0,0 +1,292 @@
+use num::clamp;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use myopic::lens::lens::*;
+use myopic::lens::*;
+
+use crate::bit_vec_scope::*;
+use crate::lens;
+use crate::optic::*;
+use crate::scope::*;
+use crate::shape::*;
+use crate::vec_scope::*;
+
+
+/* Slice Scope */
+// A SliceScope is a scope over a borrowed, non-empty slice. Splitting a
+// VecScope gives SliceScopes over disjoint parts of its vec, each with its
+// own cursor, which can be handed to different threads.
+pub struct SliceScope<'a, A> {
+    pub slice: &'a mut [A],
+    pub pos: usize,
+}
+
+impl<'a, A> SliceScope<'a, A> {
+    pub fn with_slice(slice: &'a mut [A]) -> Option<SliceScope<'a, A>> {
+        if !slice.is_empty() {
+            Some(SliceScope {
+                slice,
+                pos: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    // split into [0, mid) and [mid, len), if both are non-empty
+    pub fn split_at(self, mid: usize) -> Option<(SliceScope<'a, A>, SliceScope<'a, A>)> {
+        if mid == 0 || mid >= self.slice.len() {
+            return None;
+        }
+
+        let (left, right) = self.slice.split_at_mut(mid);
+        Some((SliceScope { slice: left, pos: 0 }, SliceScope { slice: right, pos: 0 }))
+    }
+
+    pub fn chunks(self, size: usize) -> impl Iterator<Item=SliceScope<'a, A>> {
+        assert!(size > 0, "chunk size must be nonzero");
+        self.slice.chunks_mut(size).map(|chunk| SliceScope { slice: chunk, pos: 0 })
+    }
+}
+
+impl<'a, A: Copy> SliceScope<'a, A> {
+    pub fn lens() -> impl Optical<Input=SliceScope<'a, A>, Output=A> + SliceLens {
+        let lens: Lens<_, _, SliceScope<'a, A>, A> =
+            Lens::new(|slice: &SliceScope<'a, A>| slice.slice[slice.pos],
+                      |slice: &mut SliceScope<'a, A>, a: A| slice.slice[slice.pos] = a);
+
+        lens
+    }
+}
+
+impl<A> VecScope<A> {
+    pub fn split_at_mut(&mut self, mid: usize) -> Option<(SliceScope<'_, A>, SliceScope<'_, A>)> {
+        SliceScope::with_slice(&mut self.vec)?.split_at(mid)
+    }
+
+    pub fn chunks_mut(&mut self, size: usize) -> impl Iterator<Item=SliceScope<'_, A>> {
+        assert!(size > 0, "chunk size must be nonzero");
+        self.vec.chunks_mut(size).map(|chunk| SliceScope { slice: chunk, pos: 0 })
+    }
+}
+
+impl<'a, A> Shape for SliceScope<'a, A> {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.slice.len()
+    }
+}
+
+impl<'a, A> Position for SliceScope<'a, A> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl<'a, A> Scope<usize> for SliceScope<'a, A> {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.slice.len() - 1);
+    }
+}
+
+impl<'a, A> Scope<isize> for SliceScope<'a, A> {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.slice.len() - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!(['a, A] SliceScope<'a, A>);
+
+
+/* Bit Slice Scope */
+// A BitSliceScope is a scope over the bits [start, start + len) of a
+// borrowed byte slice. Splits of a BitVecScope need not fall on a byte
+// boundary, in which case both halves hold the byte containing the split.
+// To keep that safe the bytes are viewed as atomics, and each half only
+// ever changes its own bits with an atomic and/or, so halves on different
+// threads never disturb each other. Splitting consumes the scope, so no
+// two scopes ever cover the same bit.
+pub struct BitSliceScope<'a> {
+    bytes: &'a [AtomicU8],
+    start: usize,
+    len: usize,
+    pub pos: usize,
+}
+
+impl<'a> BitSliceScope<'a> {
+    fn new(bytes: &'a [AtomicU8], start: usize, len: usize) -> BitSliceScope<'a> {
+        // keep only the bytes this scope covers
+        let first = start / 8;
+        let last = (start + len).div_ceil(8);
+        BitSliceScope {
+            bytes: &bytes[first..last],
+            start: start % 8,
+            len,
+            pos: 0,
+        }
+    }
+
+    pub fn lens() -> lens::Lens<BitSliceScope<'a>, bool> {
+        lens::lens(Rc::new(|bits: &BitSliceScope<'a>| bits.get(bits.pos)),
+                   Rc::new(|bits: &mut BitSliceScope<'a>, a: bool| { let pos = bits.pos; bits.set(pos, a) }))
+    }
+
+    // the bit at pos, which must be less than the scope's length, as the
+    // neighbouring scope may own the bits past it
+    pub fn get(&self, pos: usize) -> bool {
+        assert!(pos < self.len, "bit {} out of range for a slice of {} bits", pos, self.len);
+        let bit = self.start + pos;
+        (self.bytes[bit / 8].load(Ordering::Relaxed) & (1 << (bit % 8))) != 0
+    }
+
+    pub fn set(&mut self, pos: usize, a: bool) {
+        assert!(pos < self.len, "bit {} out of range for a slice of {} bits", pos, self.len);
+        let bit = self.start + pos;
+        if a {
+            self.bytes[bit / 8].fetch_or(1 << (bit % 8), Ordering::Relaxed);
+        } else {
+            self.bytes[bit / 8].fetch_and(!(1 << (bit % 8)), Ordering::Relaxed);
+        }
+    }
+
+    // split into bits [0, mid) and [mid, len), if both are non-empty
+    pub fn split_at(self, mid: usize) -> Option<(BitSliceScope<'a>, BitSliceScope<'a>)> {
+        if mid == 0 || mid >= self.len {
+            return None;
+        }
+
+        Some((BitSliceScope::new(self.bytes, self.start, mid),
+              BitSliceScope::new(self.bytes, self.start + mid, self.len - mid)))
+    }
+
+    pub fn chunks(self, size: usize) -> impl Iterator<Item=BitSliceScope<'a>> {
+        assert!(size > 0, "chunk size must be nonzero");
+        let BitSliceScope { bytes, start, len, .. } = self;
+        (0..len).step_by(size).map(move |offset| BitSliceScope::new(bytes, start + offset, size.min(len - offset)))
+    }
+}
+
+// View a mutably borrowed byte slice as atomics, for as long as the borrow.
+fn as_atomic(bytes: &mut [u8]) -> &[AtomicU8] {
+    // SAFETY: AtomicU8 has the same size and alignment as u8, and the
+    // exclusive borrow guarantees no other access for the returned lifetime.
+    unsafe { &*(bytes as *mut [u8] as *const [AtomicU8]) }
+}
+
+impl BitVecScope {
+    pub fn split_at_mut(&mut self, mid: usize) -> Option<(BitSliceScope<'_>, BitSliceScope<'_>)> {
+        let len = self.shape();
+        BitSliceScope::new(as_atomic(&mut self.bytes), 0, len).split_at(mid)
+    }
+
+    pub fn chunks_mut(&mut self, size: usize) -> impl Iterator<Item=BitSliceScope<'_>> {
+        let len = self.shape();
+        BitSliceScope::new(as_atomic(&mut self.bytes), 0, len).chunks(size)
+    }
+}
+
+impl<'a> Shape for BitSliceScope<'a> {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a> Position for BitSliceScope<'a> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl<'a> Scope<usize> for BitSliceScope<'a> {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.len - 1);
+    }
+}
+
+impl<'a> Scope<isize> for BitSliceScope<'a> {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.len - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!(['a] BitSliceScope<'a>);
+
+#[test]
+fn test_vec_scope_split() {
+    let mut vec_scope = VecScope::with_vec((0..10u32).collect()).unwrap();
+
+    {
+        let (mut left, mut right) = vec_scope.split_at_mut(4).unwrap();
+        assert_eq!((left.shape(), right.shape()), (4, 6));
+
+        right.adjust(100usize);
+        SliceScope::lens().set(&mut right, 99);
+
+        std::thread::scope(|threads| {
+            threads.spawn(move || scope_map(&mut left, Action::new(SliceScope::lens(), |val| val * 10)));
+            threads.spawn(move || {
+                let transform = Transform::make_transform(SliceScope::lens(), vec!(0usize, 1), |val| val + 100);
+                transform.transform(&mut right);
+            });
+        });
+    }
+    assert_eq!(vec_scope.vec, vec!(0, 10, 20, 30, 104, 105, 6, 7, 8, 99));
+
+    let chunk_sums: Vec<u32> = vec_scope.chunks_mut(4).map(|chunk| chunk.slice.iter().sum()).collect();
+    assert_eq!(chunk_sums, vec!(60, 222, 107));
+    assert!(vec_scope.split_at_mut(10).is_none());
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| vec_scope.chunks_mut(0).count())).is_err());
+}
+
+#[test]
+fn test_bit_vec_scope_split() {
+    let mut bit_vec_scope = BitVecScope::with_bytes(vec!(0; 4)).unwrap();
+
+    {
+        // split inside the second byte, and fill both halves at once
+        let (mut left, mut right) = bit_vec_scope.split_at_mut(13).unwrap();
+        assert_eq!((left.shape(), right.shape()), (13, 19));
+
+        std::thread::scope(|threads| {
+            threads.spawn(move || {
+                let lens = BitSliceScope::lens();
+                for pos in (0..13).step_by(2) {
+                    left.adjust(pos as usize);
+                    (lens.set)(&mut left, true);
+                }
+            });
+            threads.spawn(move || {
+                let lens = BitSliceScope::lens();
+                for _ in 0..19 {
+                    (lens.set)(&mut right, true);
+                    right.adjust(1isize);
+                }
+            });
+        });
+    }
+    assert_eq!(bit_vec_scope.bytes, vec!(0x55, 0xF5, 0xFF, 0xFF));
+
+    let mut chunks: Vec<BitSliceScope> = bit_vec_scope.chunks_mut(10).collect();
+    assert_eq!(chunks.iter().map(|chunk| chunk.shape()).collect::<Vec<_>>(), vec!(10, 10, 10, 2));
+    chunks[1].set(0, false);
+    chunks[1].set(9, false);
+    assert!(chunks[0].get(8));
+    assert!(!chunks[0].get(9));
+    assert!(!chunks[1].get(0));
+
+    let (first, _) = chunks.remove(3).split_at(1).unwrap();
+    assert!(first.get(0));
+    drop(chunks);
+    assert_eq!(bit_vec_scope.bytes, vec!(0x55, 0xF1, 0xF7, 0xFF));
+
+    // the bits past a scope's end belong to its neighbour
+    let (mut left, _) = bit_vec_scope.split_at_mut(13).unwrap();
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| left.get(13))).is_err());
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| left.set(13, false))).is_err());
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| bit_vec_scope.chunks_mut(0).count())).is_err());
+    assert_eq!(bit_vec_scope.bytes, vec!(0x55, 0xF1, 0xF7, 0xFF));
+}
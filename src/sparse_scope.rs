@@ -0,0 +1,197 @@
+use num::clamp;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::iter::FromIterator;
+
+use myopic::lens::lens::*;
+use myopic::lens::*;
+
+use crate::optic::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+/* Sparse Scope */
+// A SparseScope presents len logical elements but only stores the ones
+// that differ from its default value, keyed by position. Writing the
+// default to a position removes its entry.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SparseScope<A> {
+    entries: BTreeMap<usize, A>,
+    default: A,
+    len: usize,
+    pos: usize,
+}
+
+impl<A: Copy + PartialEq> SparseScope<A> {
+    pub fn new(default: A, len: usize) -> Option<SparseScope<A>> {
+        if len > 0 {
+            Some(SparseScope {
+                entries: BTreeMap::new(),
+                default,
+                len,
+                pos: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn default_value(&self) -> A {
+        self.default
+    }
+
+    pub fn get(&self, pos: usize) -> A {
+        match self.entries.get(&pos) {
+            Some(a) => *a,
+            None => self.default,
+        }
+    }
+
+    pub fn insert(&mut self, pos: usize, a: A) {
+        assert!(pos < self.len, "SparseScope position {} out of range for length {}", pos, self.len);
+        if a == self.default {
+            self.entries.remove(&pos);
+        } else {
+            self.entries.insert(pos, a);
+        }
+    }
+
+    // number of stored, non-default entries
+    pub fn stored(&self) -> usize {
+        self.entries.len()
+    }
+
+    // stored entries in increasing position order
+    pub fn entries(&self) -> btree_map::Iter<'_, usize, A> {
+        self.entries.iter()
+    }
+
+    // stored entries at or after start
+    pub fn entries_from(&self, start: usize) -> btree_map::Range<'_, usize, A> {
+        self.entries.range(start..)
+    }
+
+    // positions of the stored entries, sorted, for use as Transform indices
+    pub fn indices<'a>(&'a self) -> impl Iterator<Item=usize> + 'a {
+        self.entries.keys().cloned()
+    }
+
+    pub fn to_vec(&self) -> Vec<A> {
+        let mut vec = vec!(self.default; self.len);
+        for (pos, a) in self.entries.iter() {
+            vec[*pos] = *a;
+        }
+        vec
+    }
+
+    pub fn lens() -> impl Optical<Input=SparseScope<A>, Output=A> + SliceLens {
+        let lens: Lens<_, _, SparseScope<A>, A> =
+            Lens::new(|sparse: &SparseScope<A>| get_sparse_scope(sparse),
+                      |sparse: &mut SparseScope<A>, a: A| set_sparse_scope(sparse, a));
+
+        lens
+    }
+}
+
+// elements equal to A's default are not stored. Panics if the iterator is
+// empty.
+impl<A: Copy + PartialEq + Default> FromIterator<A> for SparseScope<A> {
+    fn from_iter<It: IntoIterator<Item=A>>(iter: It) -> SparseScope<A> {
+        let default = A::default();
+        let mut entries = BTreeMap::new();
+        let mut len = 0;
+        for a in iter {
+            if a != default {
+                entries.insert(len, a);
+            }
+            len += 1;
+        }
+
+        let mut sparse_scope = SparseScope::new(default, len).expect("SparseScope needs at least one element");
+        sparse_scope.entries = entries;
+        sparse_scope
+    }
+}
+
+impl<A> Shape for SparseScope<A> {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.len
+    }
+}
+
+impl<A> Position for SparseScope<A> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+pub fn get_sparse_scope<A: Copy + PartialEq>(sparse_scope: &SparseScope<A>) -> A {
+    sparse_scope.get(sparse_scope.pos)
+}
+
+pub fn set_sparse_scope<A: Copy + PartialEq>(sparse_scope: &mut SparseScope<A>, a: A) {
+    let pos = sparse_scope.pos;
+    sparse_scope.insert(pos, a);
+}
+
+impl<A: Copy> Scope<usize> for SparseScope<A> {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.len - 1);
+    }
+}
+
+impl<A: Copy> Scope<isize> for SparseScope<A> {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.len - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!([A: Copy] SparseScope<A>);
+
+#[test]
+fn test_sparse_scope() {
+    let mut sparse_scope = SparseScope::new(0u32, 10).unwrap();
+    let sparse_lens = SparseScope::lens();
+
+    assert_eq!(sparse_scope.shape(), 10);
+    assert_eq!(sparse_lens.get(&sparse_scope), 0);
+
+    sparse_scope.adjust(3usize);
+    sparse_lens.set(&mut sparse_scope, 7);
+    sparse_scope.adjust(100usize);
+    sparse_lens.set(&mut sparse_scope, 9);
+    assert_eq!(sparse_lens.get(&sparse_scope), 9);
+    assert_eq!(sparse_scope.stored(), 2);
+    assert_eq!(sparse_scope.to_vec(), vec!(0, 0, 0, 7, 0, 0, 0, 0, 0, 9));
+
+    // writing the default removes the entry
+    sparse_scope.adjust(-6isize);
+    sparse_lens.set(&mut sparse_scope, 0);
+    assert_eq!(sparse_scope.stored(), 1);
+    assert_eq!(sparse_scope.entries().collect::<Vec<_>>(), vec!((&9, &9)));
+    assert_eq!(sparse_scope.entries_from(10).count(), 0);
+
+    let collected: SparseScope<u32> = sparse_scope.to_vec().into_iter().collect();
+    assert_eq!(collected.shape(), 10);
+    assert!(collected.entries().eq(sparse_scope.entries()));
+}
+
+#[test]
+fn test_sparse_scope_transform() {
+    let length = 100_000_000;
+    let mut sparse_scope = SparseScope::new(0u32, length).unwrap();
+
+    let transform =
+        Transform::make_transform(SparseScope::lens(), (0..length).step_by(10_000_000), |val| val + 2);
+    transform.transform(&mut sparse_scope);
+    assert_eq!(sparse_scope.stored(), 10);
+
+    // use the stored entries as the index stream of another transform
+    let indices: Vec<usize> = sparse_scope.indices().collect();
+    let transform = Transform::make_transform(SparseScope::lens(), indices, |val| val - 2);
+    transform.transform(&mut sparse_scope);
+    assert_eq!(sparse_scope.stored(), 0);
+}
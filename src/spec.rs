@@ -0,0 +1,761 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::fmt;
+
+use num::PrimInt;
+use num::cast::NumCast;
+use num::traits::WrappingAdd;
+
+use crate::lens::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+// A small language for writing transforms as text, such as
+//
+//   every 100 from 0..1000000 of field temp: add 1
+//   [1, 5, 9] | 20..=30 of field flags: mask 0x0F
+//   (0..100 - every 2 from 0..100) & 10..20 of field level: clamp 0 50
+//
+// Each line (or ;-separated statement) names a set of indices, a lens from
+// a LensRegistry, and one of a fixed set of operators. Index sets are
+// ranges (.. or ..=), stepped ranges, lists and single indices, combined
+// with | (union), & (intersection) and - (difference). The set operators
+// have equal precedence and associate to the left. # starts a comment.
+
+
+/* Errors */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl SpecError {
+    fn new(message: String, span: Range<usize>) -> SpecError {
+        SpecError {
+            message,
+            span,
+        }
+    }
+
+    // the line and column (both starting from 1) where the error starts
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.span.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+        (line, col)
+    }
+
+    // render the error with the offending source line underlined
+    pub fn render(&self, source: &str) -> String {
+        let (line, col) = self.line_col(source);
+        let text = source.lines().nth(line - 1).unwrap_or("");
+        let width = self.span.end.saturating_sub(self.span.start).max(1);
+
+        format!("error: {}\n --> {}:{}\n  | {}\n  | {}{}",
+                self.message, line, col, text,
+                " ".repeat(col - 1), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.start, self.span.end)
+    }
+}
+
+
+/* Lexer */
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Int(i64),
+    Ident(String),
+    DotDot,
+    DotDotEq,
+    Comma,
+    Colon,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Pipe,
+    Amp,
+    Minus,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Int(n) => write!(f, "'{}'", n),
+            Token::Ident(name) => write!(f, "'{}'", name),
+            Token::DotDot => write!(f, "'..'"),
+            Token::DotDotEq => write!(f, "'..='"),
+            Token::Comma => write!(f, "','"),
+            Token::Colon => write!(f, "':'"),
+            Token::LBracket => write!(f, "'['"),
+            Token::RBracket => write!(f, "']'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Pipe => write!(f, "'|'"),
+            Token::Amp => write!(f, "'&'"),
+            Token::Minus => write!(f, "'-'"),
+            Token::End => write!(f, "end of statement"),
+        }
+    }
+}
+
+// a statement's tokens with their spans
+type Statement = Vec<(Token, Range<usize>)>;
+
+// split the source into statements
+fn lex(source: &str) -> Result<Vec<Statement>, SpecError> {
+    let mut statements = Vec::new();
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            '\n' | ';' => {
+                if !tokens.is_empty() {
+                    tokens.push((Token::End, start..start));
+                    statements.push(tokens);
+                    tokens = Vec::new();
+                }
+                continue;
+            }
+
+            '#' => {
+                while let Some(&(_, c)) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+                continue;
+            }
+
+            c if c.is_whitespace() => continue,
+
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '|' => Token::Pipe,
+            '&' => Token::Amp,
+            '-' => Token::Minus,
+
+            '.' => {
+                if chars.peek().map(|&(_, c)| c) != Some('.') {
+                    return Err(SpecError::new("expected '..'".to_string(), start..start + 1));
+                }
+                chars.next();
+
+                if chars.peek().map(|&(_, c)| c) == Some('=') {
+                    chars.next();
+                    tokens.push((Token::DotDotEq, start..start + 3));
+                } else {
+                    tokens.push((Token::DotDot, start..start + 2));
+                }
+                continue;
+            }
+
+            c if c.is_ascii_digit() => {
+                let mut end = start + 1;
+                while let Some(&(index, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+
+                let text = source[start..end].replace('_', "");
+                let parsed = if text.starts_with("0x") || text.starts_with("0X") {
+                    i64::from_str_radix(&text[2..], 16)
+                } else if text.starts_with("0b") || text.starts_with("0B") {
+                    i64::from_str_radix(&text[2..], 2)
+                } else {
+                    text.parse::<i64>()
+                };
+
+                match parsed {
+                    Ok(n) => tokens.push((Token::Int(n), start..end)),
+                    Err(_) => return Err(SpecError::new(format!("invalid number '{}'", &source[start..end]), start..end)),
+                }
+                continue;
+            }
+
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(index, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push((Token::Ident(source[start..end].to_string()), start..end));
+                continue;
+            }
+
+            c => return Err(SpecError::new(format!("unexpected character '{}'", c), start..start + c.len_utf8())),
+        };
+
+        tokens.push((token, start..start + c.len_utf8()));
+    }
+
+    if !tokens.is_empty() {
+        tokens.push((Token::End, source.len()..source.len()));
+        statements.push(tokens);
+    }
+
+    Ok(statements)
+}
+
+
+/* Index Expressions */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexExpr {
+    // the indices start, start + step, ... below end
+    Range { start: usize, end: usize, step: usize },
+    // a sorted list of distinct indices
+    List(Vec<usize>),
+    Union(Box<IndexExpr>, Box<IndexExpr>),
+    Intersection(Box<IndexExpr>, Box<IndexExpr>),
+    Difference(Box<IndexExpr>, Box<IndexExpr>),
+}
+
+impl IndexExpr {
+    // one past the largest index that can be produced, used to check an
+    // expression against the shape of a scope.
+    pub fn bound(&self) -> usize {
+        match self {
+            IndexExpr::Range { start, end, step } => {
+                if end > start {
+                    start + (((end - 1 - start) / step) * step) + 1
+                } else {
+                    0
+                }
+            }
+            IndexExpr::List(indices) => indices.last().map_or(0, |index| index + 1),
+            IndexExpr::Union(lhs, rhs) => lhs.bound().max(rhs.bound()),
+            IndexExpr::Intersection(lhs, rhs) => lhs.bound().min(rhs.bound()),
+            IndexExpr::Difference(lhs, _) => lhs.bound(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SetOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+// IndexIter yields the indices of an IndexExpr in increasing order, merging
+// the sorted streams of set operations lazily so large ranges are never
+// materialized.
+pub struct IndexIter {
+    inner: Box<dyn Iterator<Item=usize>>,
+}
+
+impl Iterator for IndexIter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.inner.next()
+    }
+}
+
+struct Merge {
+    op: SetOp,
+    lhs: Peekable<IndexIter>,
+    rhs: Peekable<IndexIter>,
+}
+
+impl Iterator for Merge {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            match (self.lhs.peek().cloned(), self.rhs.peek().cloned()) {
+                (None, None) => return None,
+
+                (Some(l), None) => {
+                    if self.op == SetOp::Intersection {
+                        return None;
+                    }
+                    self.lhs.next();
+                    return Some(l);
+                }
+
+                (None, Some(r)) => {
+                    if self.op != SetOp::Union {
+                        return None;
+                    }
+                    self.rhs.next();
+                    return Some(r);
+                }
+
+                (Some(l), Some(r)) => {
+                    if l < r {
+                        self.lhs.next();
+                        if self.op != SetOp::Intersection {
+                            return Some(l);
+                        }
+                    } else if r < l {
+                        self.rhs.next();
+                        if self.op == SetOp::Union {
+                            return Some(r);
+                        }
+                    } else {
+                        self.lhs.next();
+                        self.rhs.next();
+                        if self.op != SetOp::Difference {
+                            return Some(l);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl IntoIterator for IndexExpr {
+    type Item = usize;
+    type IntoIter = IndexIter;
+
+    fn into_iter(self) -> IndexIter {
+        let inner: Box<dyn Iterator<Item=usize>> = match self {
+            IndexExpr::Range { start, end, step } => Box::new((start..end).step_by(step)),
+            IndexExpr::List(indices) => Box::new(indices.into_iter()),
+            IndexExpr::Union(lhs, rhs) => merge(SetOp::Union, *lhs, *rhs),
+            IndexExpr::Intersection(lhs, rhs) => merge(SetOp::Intersection, *lhs, *rhs),
+            IndexExpr::Difference(lhs, rhs) => merge(SetOp::Difference, *lhs, *rhs),
+        };
+
+        IndexIter {
+            inner,
+        }
+    }
+}
+
+fn merge(op: SetOp, lhs: IndexExpr, rhs: IndexExpr) -> Box<dyn Iterator<Item=usize>> {
+    Box::new(Merge {
+        op,
+        lhs: lhs.into_iter().peekable(),
+        rhs: rhs.into_iter().peekable(),
+    })
+}
+
+
+/* Operators */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op<N> {
+    Set(N),
+    Add(N),
+    Xor(N),
+    Mask(N),
+    Clamp(N, N),
+}
+
+impl<N: PrimInt + WrappingAdd> Op<N> {
+    pub fn apply(&self, n: N) -> N {
+        match *self {
+            Op::Set(val) => val,
+            Op::Add(val) => n.wrapping_add(&val),
+            Op::Xor(val) => n ^ val,
+            Op::Mask(val) => n & val,
+            Op::Clamp(low, high) => n.max(low).min(high),
+        }
+    }
+}
+
+impl<I, N: PrimInt + WrappingAdd> ActionFn<I, N> for Op<N> {
+    fn call(&self, _index: I, n: N) -> N {
+        self.apply(n)
+    }
+}
+
+
+/* Parser */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransformSpec<N> {
+    pub indices: IndexExpr,
+    pub field: String,
+    pub op: Op<N>,
+    // the spans of the index expression and the field name, kept so
+    // errors found when building transforms can point back at the source
+    pub indices_span: Range<usize>,
+    pub field_span: Range<usize>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, Range<usize>)>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn span(&self) -> Range<usize> {
+        self.tokens[self.index].1.clone()
+    }
+
+    fn prev_end(&self) -> usize {
+        self.tokens[self.index.saturating_sub(1)].1.end
+    }
+
+    fn advance(&mut self) -> (Token, Range<usize>) {
+        let token = self.tokens[self.index].clone();
+        if token.0 != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, SpecError> {
+        Err(SpecError::new(format!("expected {}, found {}", expected, self.peek()), self.span()))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), SpecError> {
+        if *self.peek() == token {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(&token.to_string())
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SpecError> {
+        match self.peek() {
+            Token::Ident(name) if name == keyword => {
+                self.advance();
+                Ok(())
+            }
+            _ => self.error(&format!("'{}'", keyword)),
+        }
+    }
+
+    fn index(&mut self) -> Result<usize, SpecError> {
+        match self.advance() {
+            (Token::Int(n), _) if n >= 0 => Ok(n as usize),
+            (Token::Int(n), span) => Err(SpecError::new(format!("index {} is negative", n), span)),
+            (token, span) => Err(SpecError::new(format!("expected an index, found {}", token), span)),
+        }
+    }
+
+    fn value<N: PrimInt>(&mut self) -> Result<N, SpecError> {
+        let start = self.span().start;
+        let negative = *self.peek() == Token::Minus;
+        if negative {
+            self.advance();
+        }
+
+        match self.advance() {
+            (Token::Int(n), span) => {
+                let n = if negative { -n } else { n };
+                NumCast::from(n).ok_or_else(|| SpecError::new(format!("value {} out of range for field", n), start..span.end))
+            }
+            (token, span) => Err(SpecError::new(format!("expected a value, found {}", token), span)),
+        }
+    }
+
+    fn range(&mut self, step: usize) -> Result<IndexExpr, SpecError> {
+        let start = self.index()?;
+        let inclusive = match self.advance() {
+            (Token::DotDot, _) => false,
+            (Token::DotDotEq, _) => true,
+            (token, span) => return Err(SpecError::new(format!("expected '..' or '..=', found {}", token), span)),
+        };
+        let end = self.index()?;
+
+        Ok(IndexExpr::Range { start, end: if inclusive { end + 1 } else { end }, step })
+    }
+
+    fn term(&mut self) -> Result<IndexExpr, SpecError> {
+        match self.peek().clone() {
+            Token::Ident(ref name) if name == "every" => {
+                self.advance();
+                let span = self.span();
+                let step = self.index()?;
+                if step == 0 {
+                    return Err(SpecError::new("step must be nonzero".to_string(), span));
+                }
+                self.expect_keyword("from")?;
+                self.range(step)
+            }
+
+            Token::LBracket => {
+                self.advance();
+                let mut indices = vec!(self.index()?);
+                while *self.peek() == Token::Comma {
+                    self.advance();
+                    indices.push(self.index()?);
+                }
+                self.expect(Token::RBracket)?;
+
+                indices.sort();
+                indices.dedup();
+                Ok(IndexExpr::List(indices))
+            }
+
+            Token::LParen => {
+                self.advance();
+                let expr = self.index_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+
+            Token::Int(_) => {
+                if let Some((Token::DotDot, _)) | Some((Token::DotDotEq, _)) = self.tokens.get(self.index + 1) {
+                    self.range(1)
+                } else {
+                    Ok(IndexExpr::List(vec!(self.index()?)))
+                }
+            }
+
+            _ => self.error("an index expression"),
+        }
+    }
+
+    fn index_expr(&mut self) -> Result<IndexExpr, SpecError> {
+        let mut expr = self.term()?;
+
+        loop {
+            let op = match self.peek() {
+                Token::Pipe => SetOp::Union,
+                Token::Amp => SetOp::Intersection,
+                Token::Minus => SetOp::Difference,
+                _ => return Ok(expr),
+            };
+            self.advance();
+
+            let rhs = Box::new(self.term()?);
+            let lhs = Box::new(expr);
+            expr = match op {
+                SetOp::Union => IndexExpr::Union(lhs, rhs),
+                SetOp::Intersection => IndexExpr::Intersection(lhs, rhs),
+                SetOp::Difference => IndexExpr::Difference(lhs, rhs),
+            };
+        }
+    }
+
+    fn op<N: PrimInt>(&mut self) -> Result<Op<N>, SpecError> {
+        let (token, span) = self.advance();
+        let name = match token {
+            Token::Ident(name) => name,
+            token => return Err(SpecError::new(format!("expected an operator, found {}", token), span)),
+        };
+
+        match name.as_str() {
+            "set" => Ok(Op::Set(self.value()?)),
+            "add" => Ok(Op::Add(self.value()?)),
+            "xor" => Ok(Op::Xor(self.value()?)),
+            "mask" => Ok(Op::Mask(self.value()?)),
+            "clamp" => {
+                let low = self.value()?;
+                let high = self.value()?;
+                if low > high {
+                    return Err(SpecError::new("clamp lower bound is above upper bound".to_string(),
+                                              span.start..self.prev_end()));
+                }
+                Ok(Op::Clamp(low, high))
+            }
+            _ => Err(SpecError::new(format!("unknown operator '{}', expected one of set, add, xor, mask, clamp", name), span)),
+        }
+    }
+
+    fn statement<N: PrimInt>(&mut self) -> Result<TransformSpec<N>, SpecError> {
+        let start = self.span().start;
+        let indices = self.index_expr()?;
+        let indices_span = start..self.prev_end();
+
+        self.expect_keyword("of")?;
+        self.expect_keyword("field")?;
+
+        let (field, field_span) = match self.advance() {
+            (Token::Ident(name), span) => (name, span),
+            (token, span) => return Err(SpecError::new(format!("expected a field name, found {}", token), span)),
+        };
+
+        self.expect(Token::Colon)?;
+        let op = self.op()?;
+        self.expect(Token::End)?;
+
+        Ok(TransformSpec {
+            indices,
+            field,
+            op,
+            indices_span,
+            field_span,
+        })
+    }
+}
+
+pub fn parse_spec<N: PrimInt>(source: &str) -> Result<Vec<TransformSpec<N>>, SpecError> {
+    let mut specs = Vec::new();
+
+    for tokens in lex(source)? {
+        let mut parser = Parser {
+            tokens,
+            index: 0,
+        };
+        specs.push(parser.statement()?);
+    }
+
+    Ok(specs)
+}
+
+
+/* Interpreter */
+// LensRegistry maps field names used in specs to lenses on a scope.
+pub struct LensRegistry<D, N> {
+    lenses: HashMap<String, Lens<D, N>>,
+}
+
+impl<D, N> LensRegistry<D, N> {
+    pub fn new() -> LensRegistry<D, N> {
+        LensRegistry {
+            lenses: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, lens: Lens<D, N>) {
+        self.lenses.insert(name.to_string(), lens);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Lens<D, N>> {
+        self.lenses.get(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.lenses.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+}
+
+impl<D, N> Default for LensRegistry<D, N> {
+    fn default() -> LensRegistry<D, N> {
+        LensRegistry::new()
+    }
+}
+
+pub type SpecTransform<D, N> = Transform<Op<N>, Lens<D, N>, D, N, IndexExpr>;
+
+// build the transforms of a parsed spec, checking field names against the
+// registry and indices against the given shape.
+pub fn build_spec<D, N>(specs: Vec<TransformSpec<N>>,
+                        registry: &LensRegistry<D, N>,
+                        shape: usize) -> Result<Vec<SpecTransform<D, N>>, SpecError>
+    where N: PrimInt + WrappingAdd {
+    let mut transforms = Vec::new();
+
+    for spec in specs {
+        let lens = match registry.get(&spec.field) {
+            Some(lens) => lens.clone(),
+            None => {
+                return Err(SpecError::new(format!("unknown field '{}', expected one of: {}",
+                                                  spec.field, registry.names().join(", ")),
+                                          spec.field_span));
+            }
+        };
+
+        if spec.indices.bound() > shape {
+            return Err(SpecError::new(format!("index {} out of range for scope of {} elements",
+                                              spec.indices.bound() - 1, shape),
+                                      spec.indices_span));
+        }
+
+        transforms.push(Transform {
+            action: Action::from_action_fn::<usize>(lens, spec.op),
+            indices: spec.indices,
+        });
+    }
+
+    Ok(transforms)
+}
+
+// parse a spec, build its transforms and apply them all to d in index order.
+pub fn apply_spec<D, N>(source: &str, registry: &LensRegistry<D, N>, d: &mut D) -> Result<(), SpecError>
+    where D: Scope<usize> + Shape<Shape=usize>,
+          N: PrimInt + WrappingAdd {
+    let specs = parse_spec(source)?;
+    let transforms = build_spec(specs, registry, d.shape())?;
+    apply_many(transforms, d);
+    Ok(())
+}
+
+
+#[test]
+fn test_spec_index_exprs() {
+    let specs = parse_spec::<u8>("every 3 from 0..10 | [12, 11] of field x: add 1\n\
+                                  (0..=9 - every 2 from 0..10) & 3..8 of field x: add 1").unwrap();
+
+    let indices: Vec<usize> = specs[0].indices.clone().into_iter().collect();
+    assert_eq!(indices, vec!(0, 3, 6, 9, 11, 12));
+
+    let indices: Vec<usize> = specs[1].indices.clone().into_iter().collect();
+    assert_eq!(indices, vec!(3, 5, 7));
+}
+
+#[test]
+fn test_spec_apply() {
+    use crate::vec_scope::*;
+    use std::rc::Rc;
+
+    let mut registry = LensRegistry::new();
+    registry.insert("temp", lens(Rc::new(|vec: &VecScope<(i16, i16)>| vec.vec[vec.pos].0),
+                                 Rc::new(|vec: &mut VecScope<(i16, i16)>, a| { let pos = vec.pos; vec.vec[pos].0 = a })));
+    registry.insert("level", lens(Rc::new(|vec: &VecScope<(i16, i16)>| vec.vec[vec.pos].1),
+                                  Rc::new(|vec: &mut VecScope<(i16, i16)>, a| { let pos = vec.pos; vec.vec[pos].1 = a })));
+
+    let mut vec_scope = VecScope::with_vec(vec!((0, 10); 6)).unwrap();
+    apply_spec("every 2 from 0..6 of field temp: add 1   # comment\n\
+                0..6 of field temp: add 1; 4 of field temp: set -5\n\
+                [1, 2] of field level: clamp 0 5\n\
+                2..=3 of field level: xor 0x3",
+               &registry, &mut vec_scope).unwrap();
+
+    assert_eq!(vec_scope.vec, vec!((2, 10), (1, 5), (2, 6), (1, 9), (-5, 10), (1, 10)));
+}
+
+#[test]
+fn test_spec_errors() {
+    use crate::vec_scope::*;
+    use std::rc::Rc;
+
+    let registry: LensRegistry<VecScope<u8>, u8> = LensRegistry::new();
+    let mut vec_scope = VecScope::with_vec(vec!(0u8; 10)).unwrap();
+
+    let source = "0..5 of field x: add 1\n0..5 of fild x: add 1";
+    let err = parse_spec::<u8>(source).unwrap_err();
+    assert_eq!(err, SpecError::new("expected 'field', found 'fild'".to_string(), 31..35));
+    assert_eq!(err.line_col(source), (2, 9));
+    assert_eq!(err.render(source), "error: expected 'field', found 'fild'\n --> 2:9\n  | 0..5 of fild x: add 1\n  |         ^^^^");
+
+    let err = parse_spec::<u8>("0..5 of field x: add 300").unwrap_err();
+    assert_eq!(err.message, "value 300 out of range for field");
+    assert_eq!(err.span, 21..24);
+
+    let err = parse_spec::<u8>("0..5 of field x: inc 1").unwrap_err();
+    assert_eq!(err.span, 17..20);
+
+    let err = apply_spec("0..5 of field x: add 1", &registry, &mut vec_scope).unwrap_err();
+    assert_eq!(err.span, 14..15);
+
+    let mut registry = registry;
+    registry.insert("x", lens(Rc::new(|vec: &VecScope<u8>| get_vec_scope(vec)),
+                              Rc::new(|vec: &mut VecScope<u8>, a| set_vec_scope(vec, a))));
+    let err = apply_spec("every 2 from 0..20 of field x: add 1", &registry, &mut vec_scope).unwrap_err();
+    assert_eq!(err.message, "index 18 out of range for scope of 10 elements");
+    assert_eq!(err.span, 0..18);
+}
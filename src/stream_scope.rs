@@ -0,0 +1,312 @@
+use std::io;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use num::PrimInt;
+
+use crate::lens::*;
+use crate::scope::*;
+use crate::shape::*;
+use crate::packed_bit_scope::{get_bits, set_bits, field_value, low_bits};
+
+
+/* Stream Scope */
+// A StreamScope is a packed field scope, laid out like a PackedBitScope,
+// over bytes pulled from a reader in chunks. The cursor only moves forward,
+// apart from an optional look-back window of fields behind it. Bytes that
+// fall out of the window are written, with any changes, to the writer, so
+// memory stays bounded by the chunk size plus the window however long the
+// stream is. A field which straddles two chunks is read in whole before the
+// cursor lands on it.
+//
+// Moving the cursor back past the window clamps it to the earliest field
+// still held, and moving past the end of the stream clamps it to the last
+// field. The shape is unbounded until the end is read, so iteration over a
+// whole stream, as in scope_map and iter_with, checks the shape again after
+// each move. An empty stream holds no fields, and its lens reads zero and
+// drops writes. I/O errors stop the stream and are returned by finish.
+pub struct StreamScope<R, W> {
+    reader: R,
+    writer: W,
+    buffer: Vec<u8>,
+    // absolute byte index of buffer[0]
+    base: usize,
+    pos: usize,
+    bits_used: usize,
+    chunk_size: usize,
+    lookback: usize,
+    eof: bool,
+    error: Option<io::Error>,
+}
+
+impl<R: Read, W: Write> StreamScope<R, W> {
+    pub fn new(reader: R, writer: W, bits_used: usize, chunk_size: usize) -> StreamScope<R, W> {
+        assert!(bits_used > 0 && bits_used <= 64, "bits_used must be between 1 and 64");
+        assert!(chunk_size > 0, "chunk_size must be nonzero");
+
+        let mut stream_scope = StreamScope {
+            reader,
+            writer,
+            buffer: Vec::with_capacity(chunk_size),
+            base: 0,
+            pos: 0,
+            bits_used,
+            chunk_size,
+            lookback: 0,
+            eof: false,
+            error: None,
+        };
+        stream_scope.move_to(0);
+        stream_scope
+    }
+
+    // keep this many fields behind the cursor available
+    pub fn with_lookback(mut self, lookback: usize) -> StreamScope<R, W> {
+        self.lookback = lookback;
+        self
+    }
+
+    pub fn num_lens<N: PrimInt>() -> Lens<StreamScope<R, W>, N> {
+        lens(Rc::new(|stream: &StreamScope<R, W>| get_stream_scope_num(stream)),
+             Rc::new(|stream: &mut StreamScope<R, W>, n: N| set_stream_scope_num(stream, n)))
+    }
+
+    // bytes currently held in memory
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    // write out everything held and the rest of the reader, returning the
+    // reader and writer, or the first I/O error the stream hit.
+    pub fn finish(mut self) -> io::Result<(R, W)> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.writer.write_all(&self.buffer)?;
+        io::copy(&mut self.reader, &mut self.writer)?;
+        self.writer.flush()?;
+        Ok((self.reader, self.writer))
+    }
+
+    // the earliest field wholly held in the buffer
+    fn min_pos(&self) -> usize {
+        (self.base * 8).div_ceil(self.bits_used)
+    }
+
+    // whole fields in the stream so far
+    fn fields_read(&self) -> usize {
+        ((self.base + self.buffer.len()) * 8) / self.bits_used
+    }
+
+    fn fill_to(&mut self, end_byte: usize) {
+        if self.base + self.buffer.len() >= end_byte {
+            return;
+        }
+
+        let mut chunk = vec!(0; self.chunk_size);
+        while !self.eof && self.error.is_none() && self.base + self.buffer.len() < end_byte {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => self.error = Some(error),
+            }
+        }
+    }
+
+    fn release_before(&mut self, byte: usize) {
+        if byte <= self.base || self.error.is_some() {
+            return;
+        }
+
+        let count = byte - self.base;
+        match self.writer.write_all(&self.buffer[..count]) {
+            Ok(()) => {
+                self.buffer.drain(..count);
+                self.base = byte;
+            },
+            Err(error) => self.error = Some(error),
+        }
+    }
+
+    fn move_to(&mut self, pos: usize) {
+        let mut pos = pos.max(self.min_pos());
+
+        let end_byte = pos.saturating_add(1).saturating_mul(self.bits_used).div_ceil(8);
+        self.fill_to(end_byte);
+        if pos >= self.fields_read() {
+            pos = self.fields_read().saturating_sub(1).max(self.min_pos());
+        }
+        self.pos = pos;
+
+        let keep = pos.saturating_sub(self.lookback);
+        self.release_before((keep * self.bits_used) / 8);
+    }
+
+    // the stream ended without a whole field
+    fn is_empty(&self) -> bool {
+        self.fields_read() == 0
+    }
+
+    fn bit_pos(&self) -> usize {
+        (self.pos * self.bits_used) - (self.base * 8)
+    }
+}
+
+impl<R: Read, W: Write> Shape for StreamScope<R, W> {
+    type Shape = usize;
+
+    // the length of a stream is only known once it has been read to the
+    // end, until then the shape is unbounded.
+    fn shape(&self) -> usize {
+        if self.eof {
+            self.fields_read()
+        } else {
+            usize::MAX
+        }
+    }
+}
+
+impl<R, W> Position for StreamScope<R, W> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+pub fn get_stream_scope_num<R: Read, W: Write, N: PrimInt>(stream_scope: &StreamScope<R, W>) -> N {
+    if stream_scope.is_empty() {
+        return N::zero();
+    }
+
+    let raw = get_bits(&stream_scope.buffer, stream_scope.bit_pos(), stream_scope.bits_used);
+    field_value(raw, stream_scope.bits_used).expect("packed value does not fit in the lens type")
+}
+
+pub fn set_stream_scope_num<R: Read, W: Write, N: PrimInt>(stream_scope: &mut StreamScope<R, W>, n: N) {
+    if stream_scope.is_empty() {
+        return;
+    }
+
+    let bit_pos = stream_scope.bit_pos();
+    set_bits(&mut stream_scope.buffer, bit_pos, stream_scope.bits_used, low_bits(n));
+}
+
+impl<R: Read, W: Write> Scope<usize> for StreamScope<R, W> {
+    fn adjust(&mut self, pos: usize) {
+        self.move_to(pos);
+    }
+}
+
+impl<R: Read, W: Write> Scope<isize> for StreamScope<R, W> {
+    fn adjust(&mut self, offset: isize) {
+        let pos = if offset < 0 {
+            self.pos.saturating_sub(offset.wrapping_neg() as usize)
+        } else {
+            self.pos.saturating_add(offset as usize)
+        };
+        self.move_to(pos);
+    }
+}
+
+crate::impl_typed_indices!([R: Read, W: Write] StreamScope<R, W>);
+
+#[test]
+fn test_stream_scope_transform() {
+    use std::io::Cursor;
+    use crate::packed_bit_scope::*;
+
+    let input: Vec<u8> = (0..1000u32).map(|index| (index * 7) as u8).collect();
+
+    // 9 bit fields straddle the 5 byte chunks
+    let mut stream_scope = StreamScope::new(Cursor::new(input.clone()), Vec::new(), 9, 5);
+    let transform =
+        Transform::make_transform(StreamScope::num_lens::<u16>(), (3..800usize).step_by(7), |val| val ^ 0x1FF);
+    transform.transform(&mut stream_scope);
+    assert!(stream_scope.buffered() <= 5 + 2);
+    let (_, output) = stream_scope.finish().unwrap();
+
+    let mut packed_scope = PackedBitScope::with_words(input, 9);
+    let transform =
+        Transform::make_transform(PackedBitScope::num_lens::<u16>(), (3..800usize).step_by(7), |val| val ^ 0x1FF);
+    transform.transform(&mut packed_scope);
+
+    assert_eq!(output, packed_scope.bytes);
+}
+
+#[test]
+fn test_stream_scope_lookback() {
+    use std::io::Cursor;
+
+    let input: Vec<u8> = (0..64u8).collect();
+    let mut stream_scope = StreamScope::new(Cursor::new(input.clone()), Vec::new(), 8, 4).with_lookback(3);
+    let lens = StreamScope::num_lens::<u8>();
+
+    stream_scope.adjust(20usize);
+    assert_eq!((lens.view)(&stream_scope), 20);
+
+    // within the window
+    stream_scope.adjust(-3isize);
+    assert_eq!((lens.view)(&stream_scope), 17);
+    (lens.set)(&mut stream_scope, 0);
+
+    // past the window clamps to the earliest field held
+    stream_scope.adjust(5usize);
+    assert_eq!(stream_scope.position(), 17);
+
+    // past the end clamps to the last field
+    stream_scope.adjust(1000usize);
+    assert_eq!(stream_scope.position(), 63);
+    stream_scope.adjust(isize::MAX);
+    assert_eq!(stream_scope.position(), 63);
+    assert_eq!(stream_scope.shape(), 64);
+
+    let (_, output) = stream_scope.finish().unwrap();
+    let mut expected = input;
+    expected[17] = 0;
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_stream_scope_iter() {
+    use std::io::Cursor;
+
+    // the length is only found on reaching the end of the reader
+    let input: Vec<u8> = (0..50u8).collect();
+    let mut stream_scope = StreamScope::new(Cursor::new(input.clone()), Vec::new(), 8, 4);
+    let lens = StreamScope::num_lens::<u8>();
+    assert_eq!(stream_scope.iter_with(&lens).collect::<Vec<_>>(), input);
+    assert_eq!(stream_scope.shape(), 50);
+    assert_eq!(stream_scope.seek(SeekFrom::End(-1)), Abs(49));
+
+    // the end is not known until it has been read
+    let mut unread = StreamScope::new(Cursor::new(input), Vec::new(), 8, 4);
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unread.seek(SeekFrom::End(-1)))).is_err());
+
+    let mut empty = StreamScope::new(Cursor::new(Vec::new()), Vec::new(), 8, 4);
+    assert_eq!(empty.iter_with(&lens).count(), 0);
+}
+
+#[test]
+fn test_stream_scope_map() {
+    use std::io::Cursor;
+
+    // 4 bit signed fields, to the end of a stream of unknown length
+    let input: Vec<u8> = vec!(0x21, 0xF3, 0x8E);
+    let mut stream_scope = StreamScope::new(Cursor::new(input.clone()), Vec::new(), 4, 2);
+    let lens = StreamScope::num_lens::<i8>();
+    assert_eq!(stream_scope.iter_with(&lens).collect::<Vec<_>>(), vec!(1, 2, 3, -1, -2, -8));
+
+    let mut stream_scope = StreamScope::new(Cursor::new(input), Vec::new(), 4, 2);
+    scope_map(&mut stream_scope, Action::new(StreamScope::num_lens::<i8>(), |val: i8| val + 1));
+    assert_eq!(stream_scope.position(), 5);
+    let (_, output) = stream_scope.finish().unwrap();
+    assert_eq!(output, vec!(0x32, 0x04, 0x9F));
+
+    // an empty stream has nothing to map over, and its lens reads zero
+    let mut empty = StreamScope::new(Cursor::new(Vec::new()), Vec::new(), 8, 4);
+    scope_map(&mut empty, Action::new(StreamScope::num_lens::<u8>(), |val: u8| val + 1));
+    assert_eq!((lens.view)(&StreamScope::new(Cursor::new(Vec::new()), Vec::new(), 4, 2)), 0);
+    (StreamScope::num_lens::<u8>().set)(&mut empty, 5);
+    assert_eq!(empty.finish().unwrap().1, Vec::<u8>::new());
+}
@@ -0,0 +1,138 @@
+use std::rc::Rc;
+
+use num::clamp;
+use num::PrimInt;
+
+use crate::lens::*;
+use crate::packed_bit_scope::{get_bits, set_bits, field_value, low_bits};
+use crate::scope::*;
+use crate::shape::*;
+
+
+/* Strided Packed Scope */
+// A StridedPackedScope views one field of an array of fixed-size bit
+// records. Record i's field is bits_used bits wide and starts at bit
+// offset + (i * stride), with bits numbered least significant first within
+// each byte as in PackedBitScope. The cursor walks records, and writes
+// leave the other bits of each record untouched.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StridedPackedScope {
+    pub bytes: Vec<u8>,
+    pub pos: usize,
+    pub offset: usize,
+    pub stride: usize,
+    pub bits_used: usize,
+}
+
+impl StridedPackedScope {
+    pub fn with_records(bytes: Vec<u8>, offset: usize, stride: usize, bits_used: usize) -> Option<StridedPackedScope> {
+        if stride == 0 || bits_used == 0 || bits_used > 64 || offset + bits_used > bytes.len() * 8 {
+            return None;
+        }
+
+        Some(StridedPackedScope {
+            bytes,
+            pos: 0,
+            offset,
+            stride,
+            bits_used,
+        })
+    }
+
+    pub fn num_lens<N: PrimInt>() -> Lens<StridedPackedScope, N> {
+        lens(Rc::new(|scope: &StridedPackedScope| get_strided_packed_scope_num(scope)),
+             Rc::new(|scope: &mut StridedPackedScope, n: N| set_strided_packed_scope_num(scope, n)))
+    }
+
+    pub fn bit_pos(&self) -> usize {
+        self.offset + (self.pos * self.stride)
+    }
+}
+
+impl Shape for StridedPackedScope {
+    type Shape = usize;
+
+    // the number of records whose field lies within the buffer
+    fn shape(&self) -> usize {
+        ((self.bytes.len() * 8) - self.offset - self.bits_used) / self.stride + 1
+    }
+}
+
+impl Position for StridedPackedScope {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+// fields are read sign extended for signed N, as PackedBitScope reads them
+pub fn get_strided_packed_scope_num<N: PrimInt>(scope: &StridedPackedScope) -> N {
+    let raw = get_bits(&scope.bytes, scope.bit_pos(), scope.bits_used);
+    field_value(raw, scope.bits_used).expect("packed value does not fit in the lens type")
+}
+
+pub fn set_strided_packed_scope_num<N: PrimInt>(scope: &mut StridedPackedScope, n: N) {
+    // work on the two's complement bits of n so signed values pack as well
+    let bit_pos = scope.bit_pos();
+    set_bits(&mut scope.bytes, bit_pos, scope.bits_used, low_bits(n));
+}
+
+impl Scope<usize> for StridedPackedScope {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.shape() - 1);
+    }
+}
+
+impl Scope<isize> for StridedPackedScope {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.shape() - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!([] StridedPackedScope);
+
+#[test]
+fn test_strided_packed_scope() {
+    // four 64 bit records with a 5 bit field at bit 13
+    let mut scope = StridedPackedScope::with_records(vec!(0xFF; 32), 13, 64, 5).unwrap();
+    let lens = StridedPackedScope::num_lens::<u8>();
+
+    assert_eq!(scope.shape(), 4);
+    assert_eq!((lens.view)(&scope), 0x1F);
+
+    (lens.set)(&mut scope, 0x0A);
+    assert_eq!((lens.view)(&scope), 0x0A);
+    assert_eq!(&scope.bytes[0..3], &[0xFF, 0x5F, 0xFD]);
+
+    scope.adjust(100usize);
+    assert_eq!(scope.position(), 3);
+    (lens.set)(&mut scope, 0);
+    assert_eq!(&scope.bytes[24..27], &[0xFF, 0x1F, 0xFC]);
+    assert!(scope.bytes[8..24].iter().all(|byte| *byte == 0xFF));
+
+    assert!(StridedPackedScope::with_records(vec!(0; 2), 13, 64, 5).is_none());
+
+    // signed fields are sign extended
+    let signed_lens = StridedPackedScope::num_lens::<i8>();
+    (signed_lens.set)(&mut scope, -3);
+    assert_eq!((signed_lens.view)(&scope), -3);
+    assert_eq!((lens.view)(&scope), 0x1D);
+    (signed_lens.set)(&mut scope, 15);
+    assert_eq!((signed_lens.view)(&scope), 15);
+}
+
+#[test]
+fn test_strided_packed_scope_transform() {
+    // 96 bit records, one 12 bit column at bit 40
+    let mut scope = StridedPackedScope::with_records(vec!(0; 12 * 100), 40, 96, 12).unwrap();
+    assert_eq!(scope.shape(), 100);
+
+    let transform = Transform::make_indexed_transform(StridedPackedScope::num_lens::<u16>(), 0..100usize,
+                                                      |index, val: u16| val + (index as u16 * 40));
+    transform.transform(&mut scope);
+
+    for record in 0..100 {
+        assert_eq!(get_bits(&scope.bytes, (record * 96) + 40, 12), (record as u64 * 40));
+        assert_eq!(get_bits(&scope.bytes, record * 96, 40), 0);
+        assert_eq!(get_bits(&scope.bytes, (record * 96) + 52, 44), 0);
+    }
+}
@@ -0,0 +1,227 @@
+use myopic::*;
+
+use crate::optic::*;
+use crate::scope::*;
+use crate::shape::*;
+
+
+// A fallible action function. As with ActionFn, plain closures ignore the
+// index and closures wrapped in Indexed receive it.
+pub trait TryActionFn<I, A, E> {
+    fn try_call(&self, index: I, a: A) -> Result<A, E>;
+}
+
+impl<I, A, E, F> TryActionFn<I, A, E> for F
+    where F: Fn(A) -> Result<A, E> {
+    fn try_call(&self, _index: I, a: A) -> Result<A, E> {
+        (self)(a)
+    }
+}
+
+impl<I, A, E, F> TryActionFn<I, A, E> for Indexed<F>
+    where F: Fn(I, A) -> Result<A, E> {
+    fn try_call(&self, index: I, a: A) -> Result<A, E> {
+        (self.0)(index, a)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransformError<I, E> {
+    pub index: I,
+    pub error: E,
+}
+
+// The journal records the prior values of every focus written during a
+// transaction, grouped by the index they were written at, so they can be
+// restored in reverse order if the transaction fails.
+pub struct Journal<I, A> {
+    entries: Vec<(I, Vec<A>)>,
+}
+
+impl<I: Copy, A: Clone> Journal<I, A> {
+    pub fn new() -> Journal<I, A> {
+        Journal {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // apply a fallible action at the current position of d. If the action
+    // fails on any focus, the foci already written at this position are
+    // put back before returning the error.
+    pub fn try_act<F, O, D, E>(&mut self, action: &Action<F, O, D, A>, index: I, d: &mut D) -> Result<(), E>
+        where O: Over + Lensable<Input=D, Output=A>,
+              F: TryActionFn<I, A, E> {
+        let mut priors = Vec::new();
+        let mut error = None;
+
+        action.lens.over(d, |a| {
+            if error.is_some() {
+                return a;
+            }
+
+            match action.act.try_call(index, a.clone()) {
+                Ok(new_a) => {
+                    priors.push(a);
+                    new_a
+                }
+
+                Err(err) => {
+                    error = Some(err);
+                    a
+                }
+            }
+        });
+
+        match error {
+            Some(err) => {
+                restore(&action.lens, d, priors);
+                Err(err)
+            }
+
+            None => {
+                self.entries.push((index, priors));
+                Ok(())
+            }
+        }
+    }
+
+    // restore every recorded value, newest first, and clear the journal.
+    pub fn rollback<O, D>(&mut self, lens: &O, d: &mut D)
+        where O: Over + Lensable<Input=D, Output=A>,
+              D: Scope<I> {
+        while let Some((index, priors)) = self.entries.pop() {
+            d.adjust(index);
+            restore(lens, d, priors);
+        }
+    }
+
+    pub fn commit(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<I: Copy, A: Clone> Default for Journal<I, A> {
+    fn default() -> Journal<I, A> {
+        Journal::new()
+    }
+}
+
+fn restore<O, D, A>(lens: &O, d: &mut D, priors: Vec<A>)
+    where O: Over + Lensable<Input=D, Output=A> {
+    let mut priors = priors.into_iter();
+    lens.over(d, |a| priors.next().unwrap_or(a));
+}
+
+impl<F, O, D, I, A, Ix> Transform<F, O, D, A, Ix>
+    where O: Over + Lensable<Input=D, Output=A>,
+          D: Shape + Scope<I>,
+          I: Copy,
+          A: Clone,
+          Ix: Clone + IntoIterator<Item=I> {
+    pub fn make_try_transform<E>(lens: O, indices: Ix, f: F) -> Transform<F, O, D, A, Ix>
+        where F: Fn(A) -> Result<A, E> {
+        Transform {
+            action: Action::new_fallible(lens, f),
+            indices,
+        }
+    }
+
+    // apply a fallible transform as a transaction- either every index is
+    // updated, or the scope is left as it was and the failing index is
+    // returned with the error.
+    pub fn try_transform<E>(&self, d: &mut D) -> Result<(), TransformError<I, E>>
+        where F: TryActionFn<I, A, E> {
+        let mut journal = Journal::new();
+
+        for index in self.indices.clone().into_iter() {
+            d.adjust(index);
+
+            if let Err(error) = journal.try_act(&self.action, index, d) {
+                journal.rollback(&self.action.lens, d);
+                return Err(TransformError { index, error });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn try_apply_many<F, O, D, A, Ix, I, E>(transforms: &[Transform<F, O, D, A, Ix>], d: &mut D) -> Result<(), TransformError<I, E>>
+    where D: Scope<I> + Shape,
+          Ix: IntoIterator<Item=I> + Clone,
+          I: Ord + Copy,
+          A: Clone,
+          F: TryActionFn<I, A, E>,
+          O: Over + Lensable<Input=D, Output=A> {
+    let mut journals: Vec<Journal<I, A>> = transforms.iter().map(|_| Journal::new()).collect();
+    let mut order: Vec<usize> = Vec::new();
+
+    let ix_vec = transforms.iter().map(|transform| transform.indices.clone().into_iter()).collect();
+
+    for (ix, index) in MergedIndices::new(ix_vec) {
+        d.adjust(ix);
+
+        if let Err(error) = journals[index].try_act(&transforms[index].action, ix, d) {
+            // undo in the reverse of the order the writes were made, as
+            // several transforms may have written the same position.
+            while let Some(index) = order.pop() {
+                let (ix, priors) = journals[index].entries.pop().unwrap();
+                d.adjust(ix);
+                restore(&transforms[index].action.lens, d, priors);
+            }
+
+            return Err(TransformError { index: ix, error });
+        }
+
+        order.push(index);
+    }
+
+    Ok(())
+}
+
+
+#[test]
+fn test_try_transform_rollback() {
+    use crate::vec_scope::*;
+
+    let mut vec_scope = VecScope::with_vec(vec!(1u8, 2, 3, 250, 5)).unwrap();
+
+    let transform =
+        Transform::make_try_transform(VecScope::lens(), 0..5usize,
+                                      |val: u8| val.checked_add(10).ok_or("overflow"));
+
+    let result = transform.try_transform(&mut vec_scope);
+    assert_eq!(result, Err(TransformError { index: 3, error: "overflow" }));
+    assert_eq!(vec_scope.vec, vec!(1, 2, 3, 250, 5));
+
+    vec_scope.vec[3] = 4;
+    assert_eq!(transform.try_transform(&mut vec_scope), Ok(()));
+    assert_eq!(vec_scope.vec, vec!(11, 12, 13, 14, 15));
+}
+
+#[test]
+fn test_try_apply_many_rollback() {
+    use crate::vec_scope::*;
+
+    let mut vec_scope = VecScope::with_vec(vec!(0u8; 4)).unwrap();
+
+    let check = |val: u8| if val < 2 { Ok(val + 1) } else { Err(val) };
+    let transforms = vec!(Transform::make_try_transform(VecScope::lens(), vec!(0usize, 1, 2), check),
+                          Transform::make_try_transform(VecScope::lens(), vec!(0usize, 1, 2, 3), check),
+                          Transform::make_try_transform(VecScope::lens(), vec!(0usize, 3), check));
+
+    let result = try_apply_many(&transforms, &mut vec_scope);
+    assert_eq!(result, Err(TransformError { index: 0, error: 2 }));
+    assert_eq!(vec_scope.vec, vec!(0, 0, 0, 0));
+
+    assert_eq!(try_apply_many(&transforms[0..2], &mut vec_scope), Ok(()));
+    assert_eq!(vec_scope.vec, vec!(2, 2, 2, 1));
+}
@@ -1,15 +1,22 @@
 use num::clamp;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
 use std::rc::Rc;
 
 use myopic::lens::lens::*;
 use myopic::lens::*;
 
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::optic::*;
 use crate::scope::*;
 use crate::shape::*;
 
 
 /* Vec Scope */
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct VecScope<A> {
     pub vec: Vec<A>,
     pub pos: usize,
@@ -28,6 +35,13 @@ impl<A> VecScope<A> {
     }
 }
 
+// panics if the iterator is empty, as a scope needs at least one element
+impl<A> FromIterator<A> for VecScope<A> {
+    fn from_iter<It: IntoIterator<Item=A>>(iter: It) -> VecScope<A> {
+        VecScope::with_vec(iter.into_iter().collect()).expect("VecScope needs at least one element")
+    }
+}
+
 impl<A> Shape for VecScope<A> {
     type Shape = usize;
 
@@ -37,12 +51,66 @@ impl<A> Shape for VecScope<A> {
 }
 
 impl<A: Copy> VecScope<A> {
-    pub fn lens() -> impl Optical<Input=VecScope<A>, Output=A> {
-        let lens: Lens<_, _, VecScope<A>, A> =
-            Lens::new(|vec: &VecScope<A>| get_vec_scope(vec),
-                      |vec: &mut VecScope<A>, a: A| set_vec_scope(vec, a));
+    pub fn lens() -> VecLens<A> {
+        VecLens(PhantomData)
+    }
+}
+
+// The element lens of a VecScope. It is a named type rather than a closure
+// lens so that its SliceLens impl can loop over the vec directly when
+// scope_map or Transform::transform map through it.
+pub struct VecLens<A>(PhantomData<A>);
 
-        return lens;
+impl<A> Lensable for VecLens<A> {
+    type Input = VecScope<A>;
+    type Output = A;
+}
+
+impl<A: Copy> Getter for VecLens<A> {
+    fn get(&self, vec_scope: &VecScope<A>) -> A {
+        get_vec_scope(vec_scope)
+    }
+}
+
+impl<A> Setter for VecLens<A> {
+    fn set(&self, vec_scope: &mut VecScope<A>, a: A) {
+        set_vec_scope(vec_scope, a)
+    }
+}
+
+impl<A: Copy> Optical for VecLens<A> {
+}
+
+impl<A: Copy> SliceLens for VecLens<A> {
+    fn over_all<F>(&self, vec_scope: &mut VecScope<A>, mut f: F) -> bool
+        where F: FnMut(usize, A) -> A {
+        // an empty vec has nothing to map, and no last element for the cursor
+        if vec_scope.vec.is_empty() {
+            return true;
+        }
+
+        for (index, a) in vec_scope.vec.iter_mut().enumerate() {
+            *a = f(index, *a);
+        }
+        vec_scope.pos = vec_scope.vec.len() - 1;
+        true
+    }
+
+    fn over_each<I, Ix, F>(&self, vec_scope: &mut VecScope<A>, indices: Ix, mut f: F) -> bool
+        where VecScope<A>: Scope<I>,
+              I: Copy,
+              Ix: IntoIterator<Item=I>,
+              F: FnMut(I, A) -> A {
+        if vec_scope.vec.is_empty() {
+            return true;
+        }
+
+        for index in indices {
+            vec_scope.adjust(index);
+            let pos = vec_scope.pos;
+            vec_scope.vec[pos] = f(index, vec_scope.vec[pos]);
+        }
+        true
     }
 }
 
@@ -54,6 +122,12 @@ pub fn set_vec_scope<A>(vec_scope: &mut VecScope<A>, a: A) {
     vec_scope.vec[vec_scope.pos] = a;
 }
 
+impl<A> Position for VecScope<A> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
 impl <A: Copy> Scope<usize> for VecScope<A> {
     fn adjust(&mut self, pos: usize) {
         self.pos = clamp(pos, 0, self.vec.len() - 1);
@@ -66,6 +140,96 @@ impl <A: Copy> Scope<isize> for VecScope<A> {
     }
 }
 
+crate::impl_typed_indices!([A: Copy] VecScope<A>);
+
+/* Vec View */
+// A VecView is a scope over every stride-th element of a slice, such as a
+// VecScope's vec, starting at offset, for len elements. It has its own zero
+// based positions and writes straight through to the borrowed slice.
+pub struct VecView<'a, A> {
+    pub vec: &'a mut [A],
+    pub offset: usize,
+    pub stride: usize,
+    pub len: usize,
+    pub pos: usize,
+}
+
+impl<A> VecScope<A> {
+    pub fn view(&mut self, offset: usize, stride: usize, len: usize) -> Option<VecView<'_, A>> {
+        VecView::new(&mut self.vec, offset, stride, len)
+    }
+}
+
+impl<'a, A> VecView<'a, A> {
+    // None if the view is empty or reaches past the end of the slice
+    pub fn new(vec: &'a mut [A], offset: usize, stride: usize, len: usize) -> Option<VecView<'a, A>> {
+        let last = len.checked_sub(1)?.checked_mul(stride)?.checked_add(offset)?;
+        if stride == 0 || last >= vec.len() {
+            return None;
+        }
+
+        Some(VecView {
+            vec,
+            offset,
+            stride,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl<'a, A: Copy> VecView<'a, A> {
+    pub fn lens() -> impl Optical<Input=VecView<'a, A>, Output=A> + SliceLens {
+        let lens: Lens<_, _, VecView<'a, A>, A> =
+            Lens::new(|view: &VecView<'a, A>| get_vec_view(view),
+                      |view: &mut VecView<'a, A>, a: A| set_vec_view(view, a));
+
+        lens
+    }
+
+    // index into the parent vec of the cursor
+    pub fn parent_index(&self) -> usize {
+        self.offset + (self.pos * self.stride)
+    }
+}
+
+pub fn get_vec_view<A: Copy>(vec_view: &VecView<A>) -> A {
+    vec_view.vec[vec_view.parent_index()]
+}
+
+pub fn set_vec_view<A: Copy>(vec_view: &mut VecView<A>, a: A) {
+    let index = vec_view.parent_index();
+    vec_view.vec[index] = a;
+}
+
+impl<'a, A> Shape for VecView<'a, A> {
+    type Shape = usize;
+
+    fn shape(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, A> Position for VecView<'a, A> {
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl<'a, A> Scope<usize> for VecView<'a, A> {
+    fn adjust(&mut self, pos: usize) {
+        self.pos = clamp(pos, 0, self.len - 1);
+    }
+}
+
+impl<'a, A> Scope<isize> for VecView<'a, A> {
+    fn adjust(&mut self, offset: isize) {
+        self.pos = clamp((self.pos as isize) + offset, 0, (self.len - 1) as isize) as usize;
+    }
+}
+
+crate::impl_typed_indices!(['a, A] VecView<'a, A>);
+
 #[test]
 fn test_vec_scope() {
     let mut vec_scope: VecScope<usize> = VecScope::with_vec(vec![1,2,3,4,5]).unwrap();
@@ -126,6 +290,138 @@ fn test_vec_scope_fields() {
     assert_eq!(vec_pair_lens.get(&vec_scope), 3);
 }
 
+#[test]
+fn test_vec_scope_prism() {
+    use crate::optic::*;
+
+    let mut vec_scope = VecScope::with_vec(vec![Some(1), None, Some(3)]).unwrap();
+
+    let optic = ComposedOptic::new(VecScope::lens(), some_prism());
+    let transform = Transform::make_transform(optic, 0usize..3, |val: u32| val * 10);
+    transform.transform(&mut vec_scope);
+
+    assert_eq!(vec_scope.vec, vec![Some(10), None, Some(30)]);
+}
+
+#[test]
+fn test_vec_scope_indexed() {
+    let mut vec_scope = VecScope::with_vec(vec!(0usize; 6)).unwrap();
+
+    scope_map(&mut vec_scope, Action::new_indexed(VecScope::lens(), |index: usize, val| val + index * 2));
+    assert_eq!(vec_scope.vec, vec!(0, 2, 4, 6, 8, 10));
+
+    let evens = Transform::make_indexed_transform(VecScope::lens(), (0..6).step_by(2), |index, val| val + index);
+    let odds = Transform::make_indexed_transform(VecScope::lens(), (1..6).step_by(2), |_, val| val + 100);
+    apply_both(&evens, &odds, &mut vec_scope);
+    assert_eq!(vec_scope.vec, vec!(0, 102, 6, 106, 12, 110));
+
+    let set_index = |index: usize, _: usize| index;
+    let transforms = vec!(Transform::make_indexed_transform(VecScope::lens(), vec!(0, 5), set_index),
+                          Transform::make_indexed_transform(VecScope::lens(), vec!(1, 4), set_index));
+    apply_many(transforms, &mut vec_scope);
+    assert_eq!(vec_scope.vec, vec!(0, 1, 6, 106, 4, 5));
+}
+
+#[test]
+fn test_vec_scope_slice() {
+    // a closure lens has no slice fast path, so maps through it adjust the
+    // cursor for every element
+    let closure_lens = || Lens::new(|vec: &VecScope<u32>| get_vec_scope(vec),
+                                    |vec: &mut VecScope<u32>, a: u32| set_vec_scope(vec, a));
+
+    let mut vec_scope = VecScope::with_vec((0..1000u32).collect()).unwrap();
+    let mut expected = vec_scope.clone();
+
+    scope_map(&mut expected, Action::new(closure_lens(), |val| val * 3 + 1));
+    scope_map(&mut vec_scope, Action::new(VecScope::lens(), |val| val * 3 + 1));
+    assert!(vec_scope == expected);
+
+    scope_map(&mut expected, Action::new_indexed(closure_lens(), |index: usize, val| val ^ index as u32));
+    scope_map(&mut vec_scope, Action::new_indexed(VecScope::lens(), |index: usize, val| val ^ index as u32));
+    assert!(vec_scope == expected);
+    assert_eq!(vec_scope.pos, 999);
+
+    // out of range indices clamp to the last element, as adjust does
+    Transform::make_transform(closure_lens(), (5..2000usize).step_by(7), |val| val + 1).transform(&mut expected);
+    Transform::make_transform(VecScope::lens(), (5..2000usize).step_by(7), |val| val + 1).transform(&mut vec_scope);
+    assert!(vec_scope == expected);
+    assert_eq!(vec_scope.pos, 999);
+
+    // relative indices go through adjust too
+    Transform::make_transform(closure_lens(), vec!(-3isize, 1, -500), |val| val * 2).transform(&mut expected);
+    Transform::make_transform(VecScope::lens(), vec!(-3isize, 1, -500), |val| val * 2).transform(&mut vec_scope);
+    assert!(vec_scope == expected);
+    assert_eq!(vec_scope.pos, 497);
+
+    // an empty vec has nothing to map
+    let mut empty = VecScope { vec: Vec::<u32>::new(), pos: 0 };
+    scope_map(&mut empty, Action::new(VecScope::lens(), |val| val + 1));
+    Transform::make_transform(VecScope::lens(), 0..10usize, |val| val + 1).transform(&mut empty);
+    assert!(empty.vec.is_empty());
+}
+
+#[test]
+fn test_vec_view() {
+    // interleaved rgb pixels
+    let mut vec_scope = VecScope::with_vec(vec!(10u8, 20, 30, 11, 21, 31, 12, 22, 32)).unwrap();
+
+    {
+        let mut greens = vec_scope.view(1, 3, 3).unwrap();
+        let lens = VecView::lens();
+        assert_eq!(greens.shape(), 3);
+        assert_eq!(lens.get(&greens), 20);
+
+        greens.adjust(100usize);
+        assert_eq!(greens.position(), 2);
+        assert_eq!(lens.get(&greens), 22);
+        lens.set(&mut greens, 0);
+
+        scope_map(&mut greens, Action::new(VecView::lens(), |val| val + 1));
+    }
+    assert_eq!(vec_scope.vec, vec!(10, 21, 30, 11, 22, 31, 12, 1, 32));
+
+    {
+        let mut blues = vec_scope.view(2, 3, 3).unwrap();
+        let transform = Transform::make_transform(VecView::lens(), vec!(0usize, 2), |val| val * 2);
+        transform.transform(&mut blues);
+    }
+    assert_eq!(vec_scope.vec, vec!(10, 21, 60, 11, 22, 31, 12, 1, 64));
+
+    assert!(vec_scope.view(1, 3, 4).is_none());
+    assert!(vec_scope.view(8, 5, 1).is_some());
+
+    // views whose last index overflows are refused
+    assert!(vec_scope.view(usize::MAX, 1, 2).is_none());
+    assert!(vec_scope.view(1, usize::MAX, 3).is_none());
+    assert!(vec_scope.view(0, 1, 0).is_none());
+
+    // any slice can be viewed
+    let mut pixels = [1u8, 2, 3, 4];
+    let mut evens = VecView::new(&mut pixels[1..], 0, 2, 2).unwrap();
+    evens.adjust(1usize);
+    set_vec_view(&mut evens, 0);
+    assert_eq!(pixels, [1, 2, 3, 0]);
+}
+
+#[test]
+fn test_vec_scope_iter() {
+    let mut vec_scope: VecScope<u32> = (1..=5).collect();
+    let lens = VecScope::lens();
+
+    let doubled: Vec<u32> = vec_scope.iter_with(&lens).map(|val| val * 2).collect();
+    assert_eq!(doubled, vec!(2, 4, 6, 8, 10));
+    assert_eq!(vec_scope.position(), 4);
+
+    let picked: Vec<(usize, u32)> = vec_scope.iter_at(&lens, vec!(3usize, 0, 100)).collect();
+    assert_eq!(picked, vec!((3, 4), (0, 1), (100, 5)));
+
+    // reading through a composed lens, from a scope built by collect
+    let mut pairs: VecScope<(u8, char)> = vec!((1, 'a'), (2, 'b')).into_iter().collect();
+    let first = ComposedLens::new(VecScope::lens(), Lens::new(|pair: &(u8, char)| pair.0,
+                                                              |pair: &mut (u8, char), a: u8| pair.0 = a));
+    assert_eq!(pairs.iter_with(&first).sum::<u8>(), 3);
+}
+
 #[test]
 fn vec_scope_seq() {
     let length = 100_000_000;
@@ -350,3 +646,38 @@ fn vec_scope_many_5() {
     apply_many(transforms, &mut vec_scope);
 }
 */
+
+#[test]
+fn test_vec_scope_typed_indices() {
+    use crate::bit_vec_scope::*;
+    use crate::dirty::*;
+    use crate::rle_scope::*;
+
+    let mut vec_scope = VecScope::with_vec((0..10u32).collect()).unwrap();
+    let lens = VecScope::lens();
+
+    vec_scope.adjust(Abs(4));
+    vec_scope.adjust(Rel(2));
+    assert_eq!(vec_scope.tell(), Abs(6));
+    vec_scope.adjust(Rel(-20));
+    assert_eq!(vec_scope.tell(), Abs(0));
+
+    assert_eq!(vec_scope.seek(SeekFrom::End(-1)), Abs(9));
+    assert_eq!(vec_scope.seek(SeekFrom::Current(-3)), Abs(6));
+    assert_eq!(vec_scope.seek(SeekFrom::Start(100)), Abs(9));
+    assert_eq!(vec_scope.seek(SeekFrom::End(-100)), Abs(0));
+
+    let transform = Transform::make_transform(VecScope::lens(), vec!(Abs(1), Abs(8)), |val| val * 10);
+    transform.transform(&mut vec_scope);
+    assert_eq!(vec_scope.vec, vec!(0, 10, 2, 3, 4, 5, 6, 7, 80, 9));
+    vec_scope.adjust(Rel(-1));
+    assert_eq!(lens.get(&vec_scope), 7);
+
+    // every scope moves the same way, wrapped or not
+    fn last_and_back<D: Seekable>(d: &mut D) -> (Abs, Abs) {
+        (d.seek(SeekFrom::End(-1)), d.seek(SeekFrom::Current(-2)))
+    }
+    assert_eq!(last_and_back(&mut BitVecScope::with_bytes(vec!(0, 0)).unwrap()), (Abs(15), Abs(13)));
+    assert_eq!(last_and_back(&mut RleScope::from_elem(1u8, 5).unwrap()), (Abs(4), Abs(2)));
+    assert_eq!(last_and_back(&mut DirtyScope::new(vec_scope)), (Abs(9), Abs(7)));
+}
@@ -0,0 +1,60 @@
+extern crate scope;
+
+// the derive and myopic both come through scope, as they would for users
+use scope::*;
+use scope::myopic::{Getter, Setter};
+use scope::myopic::lens::lens::ComposedLens;
+
+#[test]
+fn test_vec_scope_derived_lenses() {
+    #[derive(Clone, Copy, PartialEq, Debug, Lenses)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, Lenses)]
+    struct Record {
+        id: u16,
+        pos: Point,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, Lenses)]
+    struct Pair(u8, bool);
+
+    let point = Point { x: 1, y: 2 };
+    let mut vec_scope = VecScope::with_vec(vec!(Record { id: 0, pos: point }; 4)).unwrap();
+
+    // one column of a vec of records
+    let ids = Transform::make_indexed_transform(ComposedLens::new(VecScope::lens(), Record::id_lens()),
+                                                0..4usize,
+                                                |index, _| index as u16 * 10);
+    ids.transform(&mut vec_scope);
+
+    // a field of a nested struct
+    let ys = ComposedLens::new(ComposedLens::new(VecScope::lens(), Record::pos_lens()), Point::y_lens());
+    scope_map(&mut vec_scope, Action::new(ys, |y: i32| -y));
+
+    assert_eq!(vec_scope.vec[3], Record { id: 30, pos: Point { x: 1, y: -2 } });
+    assert!(vec_scope.vec.iter().all(|record| record.pos.x == 1 && record.pos.y == -2));
+
+    // derived lenses are optics in their own right
+    fn over<O: Over>(lens: O) -> O { lens }
+    let mut record = vec_scope.vec[0];
+    over(Record::id_lens()).set(&mut record, 7);
+    assert_eq!(record.id, 7);
+
+    let mut pair = Pair(1, false);
+    Pair::lens_1().set(&mut pair, true);
+    assert_eq!((Pair::lens_0().get(&pair), pair.1), (1, true));
+
+    #[derive(Lenses)]
+    struct Tagged<T: Clone> {
+        tag: String,
+        value: T,
+    }
+
+    let mut tagged = Tagged { tag: "a".to_string(), value: 5u64 };
+    Tagged::value_lens().set(&mut tagged, 6);
+    assert_eq!((Tagged::tag_lens().get(&tagged), tagged.value), ("a".to_string(), 6));
+}